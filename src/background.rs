@@ -0,0 +1,267 @@
+use std::f64::consts::PI;
+
+use gtk::cairo::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::quadtree::{Rgba, Viewport};
+
+/// one millimetre in document units
+const MILLIMETRE: f64 = 72.0 / 25.4;
+/// line spacing of college ruled paper in document units, used until another one is chosen
+pub const DEFAULT_SPACING: f64 = 7.1 * MILLIMETRE;
+/// distance of the margin line on ruled paper from the left edge in document units
+const RULED_MARGIN: f64 = 72.0;
+/// space left and right of music staves in document units
+const STAFF_MARGIN: f64 = 36.0;
+/// width of pattern lines in document units
+const LINE_WIDTH: f64 = 0.5;
+/// radius of the dots on dotted paper in document units
+const DOT_RADIUS: f64 = 0.75;
+const LINE_COLOR: Rgba = Rgba::new(0.5, 0.7, 1.0, 1.0);
+const MARGIN_COLOR: Rgba = Rgba::new(1.0, 0.5, 0.5, 1.0);
+const DOT_COLOR: Rgba = Rgba::new(0.5, 0.5, 0.5, 1.0);
+const STAFF_COLOR: Rgba = Rgba::new(0.4, 0.4, 0.4, 1.0);
+
+/// lines printed on the paper
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+    Plain,
+    /// horizontal lines below a header with a margin line on the left
+    Ruled,
+    /// square grid
+    Graph,
+    /// grid of equilateral triangles
+    Isometric,
+    /// dots where the lines of a square grid would cross
+    Dotted,
+    /// music staves of five lines each
+    Staves,
+}
+
+/// paper colour and pattern of a page
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Background {
+    pub color: Rgba,
+    pub pattern: Pattern,
+    /// distance between neighbouring lines in document units, staff lines are half as far apart
+    pub spacing: f64,
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background {
+            color: Rgba::new(1.0, 1.0, 1.0, 1.0),
+            pattern: Pattern::Plain,
+            spacing: DEFAULT_SPACING,
+        }
+    }
+}
+
+impl Background {
+    /// fill a page of `size` with the paper colour and draw the pattern on top, `viewport` maps
+    /// onto page coordinates
+    pub fn draw(&self, cairo_context: &Context, viewport: &Viewport, size: (f64, f64)) {
+        let (width, height) = size;
+        let (x1, y1) = viewport.transform_to_viewport((0.0, 0.0));
+        let (x2, y2) = viewport.transform_to_viewport((width, height));
+        set_color(cairo_context, self.color);
+        cairo_context.rectangle(x1, y1, x2 - x1, y2 - y1);
+        if self.pattern == Pattern::Plain || self.spacing <= 0.0 {
+            cairo_context.fill().unwrap();
+            return;
+        }
+        cairo_context.save().unwrap();
+        cairo_context.clip();
+        cairo_context.paint().unwrap();
+        cairo_context.set_line_width(LINE_WIDTH / viewport.transform.m11);
+        // lines outside the visible part of the page are skipped
+        let visible = viewport.normalized();
+        let (lower, upper) = (visible.lower(), visible.upper());
+        let rows =
+            |start: f64, end: f64, spacing: f64| steps(start, end, spacing, (lower.y(), upper.y()));
+        let columns =
+            |start: f64, end: f64, spacing: f64| steps(start, end, spacing, (lower.x(), upper.x()));
+        let spacing = self.spacing;
+        match self.pattern {
+            Pattern::Plain => (),
+            Pattern::Ruled => {
+                // leave room for a title above the first line
+                for y in rows(3.0 * spacing, height - spacing, spacing) {
+                    add_line(cairo_context, viewport, (0.0, y), (width, y));
+                }
+                stroke(cairo_context, LINE_COLOR);
+                add_line(
+                    cairo_context,
+                    viewport,
+                    (RULED_MARGIN, 0.0),
+                    (RULED_MARGIN, height),
+                );
+                stroke(cairo_context, MARGIN_COLOR);
+            }
+            Pattern::Graph => {
+                for y in rows(0.0, height, spacing) {
+                    add_line(cairo_context, viewport, (0.0, y), (width, y));
+                }
+                for x in columns(0.0, width, spacing) {
+                    add_line(cairo_context, viewport, (x, 0.0), (x, height));
+                }
+                stroke(cairo_context, LINE_COLOR);
+            }
+            Pattern::Isometric => {
+                for y in rows(0.0, height, spacing * (PI / 3.0).sin()) {
+                    add_line(cairo_context, viewport, (0.0, y), (width, y));
+                }
+                // lines rising and falling at 60 degrees through every corner on the top edge
+                let run = height / (PI / 3.0).tan();
+                let first = -(run / spacing).ceil() * spacing;
+                for x in steps(first, width + run, spacing, (first, width + run)) {
+                    add_line(cairo_context, viewport, (x, 0.0), (x + run, height));
+                    add_line(cairo_context, viewport, (x, 0.0), (x - run, height));
+                }
+                stroke(cairo_context, LINE_COLOR);
+            }
+            Pattern::Dotted => {
+                let radius = DOT_RADIUS / viewport.transform.m11;
+                for y in rows(spacing, height - spacing / 2.0, spacing) {
+                    for x in columns(spacing, width - spacing / 2.0, spacing) {
+                        let (x, y) = viewport.transform_to_viewport((x, y));
+                        cairo_context.new_sub_path();
+                        cairo_context.arc(x, y, radius, 0.0, 2.0 * PI);
+                    }
+                }
+                set_color(cairo_context, DOT_COLOR);
+                cairo_context.fill().unwrap();
+            }
+            Pattern::Staves => {
+                // five lines half the spacing apart, one staff every five times the spacing
+                let mut top = 3.0 * spacing;
+                while top + 2.0 * spacing <= height - 2.0 * spacing {
+                    for i in 0..5 {
+                        let y = top + i as f64 * spacing / 2.0;
+                        let (left, right) = (STAFF_MARGIN, width - STAFF_MARGIN);
+                        add_line(cairo_context, viewport, (left, y), (right, y));
+                    }
+                    top += 5.0 * spacing;
+                }
+                stroke(cairo_context, STAFF_COLOR);
+            }
+        }
+        cairo_context.restore().unwrap();
+    }
+
+    /// fill the whole viewport with the paper colour and repeat the pattern across it, anchored at
+    /// the origin of the page `viewport` maps onto, for the infinite canvas
+    ///
+    /// Ruled paper has no header or margin here.
+    pub fn draw_unbounded(&self, cairo_context: &Context, viewport: &Viewport) {
+        set_color(cairo_context, self.color);
+        cairo_context.paint().unwrap();
+        if self.pattern == Pattern::Plain || self.spacing <= 0.0 {
+            return;
+        }
+        cairo_context.set_line_width(LINE_WIDTH / viewport.transform.m11);
+        let visible = viewport.normalized();
+        let (lower, upper) = (visible.lower(), visible.upper());
+        let (left, top, right, bottom) = (lower.x(), lower.y(), upper.x(), upper.y());
+        // every multiple of `spacing` between `start` and `end`
+        let repeat = |start: f64, end: f64, spacing: f64| {
+            steps(
+                (start / spacing).floor() * spacing,
+                end,
+                spacing,
+                (start, end),
+            )
+        };
+        let spacing = self.spacing;
+        match self.pattern {
+            Pattern::Plain => (),
+            Pattern::Ruled => {
+                for y in repeat(top, bottom, spacing) {
+                    add_line(cairo_context, viewport, (left, y), (right, y));
+                }
+                stroke(cairo_context, LINE_COLOR);
+            }
+            Pattern::Graph => {
+                for y in repeat(top, bottom, spacing) {
+                    add_line(cairo_context, viewport, (left, y), (right, y));
+                }
+                for x in repeat(left, right, spacing) {
+                    add_line(cairo_context, viewport, (x, top), (x, bottom));
+                }
+                stroke(cairo_context, LINE_COLOR);
+            }
+            Pattern::Isometric => {
+                for y in repeat(top, bottom, spacing * (PI / 3.0).sin()) {
+                    add_line(cairo_context, viewport, (left, y), (right, y));
+                }
+                // lines at 60 degrees through the multiples of the spacing on the horizontal axis,
+                // `x` is where they cross it
+                let slope = 1.0 / (PI / 3.0).tan();
+                for x in repeat(left - bottom * slope, right - top * slope, spacing) {
+                    add_line(
+                        cairo_context,
+                        viewport,
+                        (x + top * slope, top),
+                        (x + bottom * slope, bottom),
+                    );
+                }
+                for x in repeat(left + top * slope, right + bottom * slope, spacing) {
+                    add_line(
+                        cairo_context,
+                        viewport,
+                        (x - top * slope, top),
+                        (x - bottom * slope, bottom),
+                    );
+                }
+                stroke(cairo_context, LINE_COLOR);
+            }
+            Pattern::Dotted => {
+                let radius = DOT_RADIUS / viewport.transform.m11;
+                for y in repeat(top, bottom, spacing) {
+                    for x in repeat(left, right, spacing) {
+                        let (x, y) = viewport.transform_to_viewport((x, y));
+                        cairo_context.new_sub_path();
+                        cairo_context.arc(x, y, radius, 0.0, 2.0 * PI);
+                    }
+                }
+                set_color(cairo_context, DOT_COLOR);
+                cairo_context.fill().unwrap();
+            }
+            Pattern::Staves => {
+                // staves reaching into the visible area from above are included
+                for staff in repeat(top - 2.0 * spacing, bottom, 5.0 * spacing) {
+                    for i in 0..5 {
+                        let y = staff + i as f64 * spacing / 2.0;
+                        add_line(cairo_context, viewport, (left, y), (right, y));
+                    }
+                }
+                stroke(cairo_context, STAFF_COLOR);
+            }
+        }
+    }
+}
+
+/// `start` and every `spacing` after it up to `end`, limited to the `visible` range
+fn steps(start: f64, end: f64, spacing: f64, visible: (f64, f64)) -> impl Iterator<Item = f64> {
+    let first = ((visible.0.max(start) - start) / spacing).ceil() as i64;
+    let last = ((visible.1.min(end) - start) / spacing).floor() as i64;
+    (first..=last).map(move |i| start + i as f64 * spacing)
+}
+
+/// add a line between two points in page coordinates to the current path
+fn add_line(cairo_context: &Context, viewport: &Viewport, from: (f64, f64), to: (f64, f64)) {
+    let (x1, y1) = viewport.transform_to_viewport(from);
+    let (x2, y2) = viewport.transform_to_viewport(to);
+    cairo_context.move_to(x1, y1);
+    cairo_context.line_to(x2, y2);
+}
+
+fn stroke(cairo_context: &Context, color: Rgba) {
+    set_color(cairo_context, color);
+    cairo_context.stroke().unwrap();
+}
+
+fn set_color(cairo_context: &Context, color: Rgba) {
+    cairo_context.set_source_rgba(color.red, color.green, color.blue, color.alpha);
+}
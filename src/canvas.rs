@@ -0,0 +1,282 @@
+use euclid::default::Transform2D;
+use gtk::cairo::Context;
+use rstar::{Envelope, RTree, RTreeObject};
+use serde::{Deserialize, Serialize};
+
+use crate::quadtree::{Document, Element, Ink, Layer, Page, Viewport};
+
+/// how the pages of a document are arranged
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    /// sheets of paper stacked from top to bottom
+    Paged,
+    /// unbounded plane held by a single page, whose background pattern repeats across it and whose
+    /// size is only used to cut it into pages
+    Canvas,
+}
+
+/// draw the paper of `pages` as it is shown in `layout`, a canvas is covered everywhere
+pub fn draw_paper(pages: &[Page], layout: Layout, cairo_context: &Context, viewport: &Viewport) {
+    match layout {
+        Layout::Paged => pages.draw_paper(cairo_context, viewport),
+        Layout::Canvas => pages[0]
+            .background
+            .draw_unbounded(cairo_context, &pages.page_viewport(0, viewport)),
+    }
+}
+
+/// put the pages where they are shown in paged layout onto one canvas, layers with the same index
+/// are joined
+///
+/// A canvas can not show PDF backgrounds, so pages with one should stay paged.
+pub fn to_canvas(pages: &[Page]) -> Page {
+    let first = &pages[0];
+    let mut layers: Vec<Layer> = Vec::new();
+    for (index, page) in pages.iter().enumerate() {
+        let (x, y) = pages.page_origin(index);
+        let translation = Transform2D::translation(x, y);
+        for (layer_index, layer) in page.layers.iter().enumerate() {
+            if layer_index == layers.len() {
+                layers.push(Layer {
+                    elements: RTree::new(),
+                    ..layer.clone()
+                });
+            }
+            for element in &layer.elements {
+                layers[layer_index]
+                    .elements
+                    .insert(element.transformed(&translation));
+            }
+        }
+    }
+    Page {
+        pdf: None,
+        layers,
+        ..first.clone()
+    }
+}
+
+/// cut the bounding box of the content on `canvas` into pages of its size, row by row
+///
+/// Strokes are cut where they cross from one page to the next, text boxes and images go whole to
+/// the page their centre is on. Pages without content keep their place in the grid.
+pub fn to_pages(canvas: &Page) -> Vec<Page> {
+    let blank = Page {
+        layers: canvas
+            .layers
+            .iter()
+            .map(|layer| Layer {
+                elements: RTree::new(),
+                ..layer.clone()
+            })
+            .collect(),
+        ..canvas.clone()
+    };
+    let envelope = match canvas.content_envelope() {
+        Some(envelope) => envelope,
+        None => return vec![blank],
+    };
+    let (lower, upper) = (envelope.lower(), envelope.upper());
+    let (width, height) = canvas.size.dimensions();
+    let columns = ((upper.x() - lower.x()) / width).ceil().max(1.0) as usize;
+    let rows = ((upper.y() - lower.y()) / height).ceil().max(1.0) as usize;
+    let cell_of = |x: f64, y: f64| {
+        let column = ((x - lower.x()) / width).floor().max(0.0) as usize;
+        let row = ((y - lower.y()) / height).floor().max(0.0) as usize;
+        (row.min(rows - 1), column.min(columns - 1))
+    };
+    let cell_origin = |row: usize, column: usize| {
+        (
+            lower.x() + column as f64 * width,
+            lower.y() + row as f64 * height,
+        )
+    };
+    let mut pages = vec![blank; rows * columns];
+    for (layer_index, layer) in canvas.layers.iter().enumerate() {
+        for element in &layer.elements {
+            let element_envelope = element.envelope();
+            let mut parts = Vec::new();
+            match element {
+                Element::Stroke(ink) => {
+                    let (lower, upper) = (element_envelope.lower(), element_envelope.upper());
+                    let (first_row, first_column) = cell_of(lower.x(), lower.y());
+                    let (last_row, last_column) = cell_of(upper.x(), upper.y());
+                    for row in first_row..=last_row {
+                        for column in first_column..=last_column {
+                            let (x, y) = cell_origin(row, column);
+                            let cell = ((x, y), (x + width, y + height));
+                            parts.extend(
+                                clip(ink, cell)
+                                    .into_iter()
+                                    .map(|ink| (row, column, Element::Stroke(ink))),
+                            );
+                        }
+                    }
+                }
+                _ => {
+                    let center = element_envelope.center();
+                    let (row, column) = cell_of(center.x(), center.y());
+                    parts.push((row, column, element.clone()));
+                }
+            }
+            for (row, column, part) in parts {
+                let (x, y) = cell_origin(row, column);
+                pages[row * columns + column].layers[layer_index]
+                    .elements
+                    .insert(part.transformed(&Transform2D::translation(-x, -y)));
+            }
+        }
+    }
+    pages
+}
+
+/// pieces of `ink` whose points lie within the rectangle from `min` to `max`, the points where it
+/// crosses the border are interpolated
+fn clip(ink: &Ink, (min, max): ((f64, f64), (f64, f64))) -> Vec<Ink> {
+    let inside = |(x, y): (f64, f64)| min.0 <= x && x <= max.0 && min.1 <= y && y <= max.1;
+    let points: Vec<(f64, f64)> = ink.line.0.iter().map(|c| (c.x, c.y)).collect();
+    let has_pressure = ink.pressure.len() == points.len();
+    if points.len() == 1 {
+        return if inside(points[0]) {
+            vec![ink.clone()]
+        } else {
+            Vec::new()
+        };
+    }
+    let mut pieces = Vec::new();
+    let mut piece = Ink::new(ink.style);
+    let push = |piece: &mut Ink, i: usize, t: f64| {
+        let (a, b) = (points[i], points[i + 1]);
+        let point = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t).into();
+        if piece.line.0.last() == Some(&point) {
+            return;
+        }
+        piece.line.0.push(point);
+        if has_pressure {
+            let (a, b) = (ink.pressure[i], ink.pressure[i + 1]);
+            piece.pressure.push(a + (b - a) * t);
+        }
+    };
+    for i in 0..points.len() - 1 {
+        match clip_segment(points[i], points[i + 1], min, max) {
+            Some((t0, t1)) => {
+                if piece.line.0.is_empty() {
+                    push(&mut piece, i, t0);
+                }
+                push(&mut piece, i, t1);
+                if t1 < 1.0 {
+                    pieces.push(std::mem::replace(&mut piece, Ink::new(ink.style)));
+                }
+            }
+            None if !piece.line.0.is_empty() => {
+                pieces.push(std::mem::replace(&mut piece, Ink::new(ink.style)));
+            }
+            None => (),
+        }
+    }
+    pieces.push(piece);
+    // a stroke that only touches the border leaves nothing to draw
+    pieces.retain(|piece| piece.line.0.len() > 1);
+    pieces
+}
+
+/// range of the parameter `t` for which `a + t * (b - a)` lies within the rectangle from `min`
+/// to `max`, `None` if the segment misses it
+fn clip_segment(
+    a: (f64, f64),
+    b: (f64, f64),
+    min: (f64, f64),
+    max: (f64, f64),
+) -> Option<(f64, f64)> {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let (mut t0, mut t1) = (0.0f64, 1.0f64);
+    // Liang-Barsky: every border bounds `t` on one side
+    for &(p, q) in &[
+        (-dx, a.0 - min.0),
+        (dx, max.0 - a.0),
+        (-dy, a.1 - min.1),
+        (dy, max.1 - a.1),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else if p < 0.0 {
+            t0 = t0.max(q / p);
+        } else {
+            t1 = t1.min(q / p);
+        }
+    }
+    if t0 <= t1 {
+        Some((t0, t1))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::export::PaperSize;
+    use crate::quadtree::{Stroke, StrokeStyle};
+
+    fn stroke(points: &[(f64, f64)]) -> Element {
+        let mut ink = Ink::new(StrokeStyle::default());
+        for &(x, y) in points {
+            ink.add(x, y, Some(0.5));
+        }
+        Element::Stroke(ink)
+    }
+
+    fn strokes(page: &Page) -> Vec<Vec<(f64, f64)>> {
+        let mut strokes: Vec<Vec<(f64, f64)>> = page.layers[0]
+            .elements
+            .iter()
+            .map(|element| match element {
+                Element::Stroke(ink) => ink.line.0.iter().map(|c| (c.x, c.y)).collect(),
+                other => panic!("expected a stroke, found {:?}", other),
+            })
+            .collect();
+        strokes.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        strokes
+    }
+
+    #[test]
+    fn strokes_are_cut_where_they_cross_into_the_next_page() {
+        let mut canvas = Page::new(PaperSize::Custom(100.0, 100.0));
+        // with half the line width the content, and the first page, starts at (9, 9)
+        canvas.layers[0]
+            .elements
+            .insert(stroke(&[(10.0, 10.0), (150.0, 10.0), (150.0, 20.0)]));
+        let pages = to_pages(&canvas);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(strokes(&pages[0]), vec![vec![(1.0, 1.0), (100.0, 1.0)]]);
+        assert_eq!(
+            strokes(&pages[1]),
+            vec![vec![(0.0, 1.0), (41.0, 1.0), (41.0, 11.0)]]
+        );
+    }
+
+    #[test]
+    fn empty_pages_keep_their_place_in_the_grid() {
+        let mut canvas = Page::new(PaperSize::Custom(100.0, 100.0));
+        canvas.layers[0]
+            .elements
+            .insert(stroke(&[(10.0, 10.0), (20.0, 20.0)]));
+        canvas.layers[0]
+            .elements
+            .insert(stroke(&[(250.0, 250.0), (260.0, 260.0)]));
+        let pages = to_pages(&canvas);
+        // three by three pages, only the first and the last hold a stroke
+        assert_eq!(pages.len(), 9);
+        let filled: Vec<usize> = pages
+            .iter()
+            .enumerate()
+            .filter(|(_, page)| page.layers[0].elements.size() > 0)
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(filled, vec![0, 8]);
+    }
+}
@@ -0,0 +1,111 @@
+use euclid::default::Transform2D;
+use gtk::gdk::{Clipboard, ContentProvider};
+use gtk::gio::{Cancellable, MemoryOutputStream, OutputStreamSpliceFlags};
+use gtk::glib::{Bytes, SyncSender, PRIORITY_DEFAULT};
+use gtk::prelude::*;
+use rstar::RTree;
+
+use crate::export::{self, PaperSize, PngOptions};
+use crate::image::Image;
+use crate::logic::Action;
+use crate::quadtree::{Document, Element, Page};
+use crate::selection::Selection;
+
+/// private format carrying the elements as JSON, understood by every XournalR window
+pub const MIME_TYPE: &str = "application/x-xournalr-elements";
+const SVG_MIME_TYPE: &str = "image/svg+xml";
+const PNG_MIME_TYPE: &str = "image/png";
+const JPEG_MIME_TYPE: &str = "image/jpeg";
+
+/// put `elements` on the clipboard, with SVG and PNG renderings for other applications
+pub fn copy(clipboard: &Clipboard, elements: &[Element]) {
+    let mut providers = Vec::new();
+    match serde_json::to_vec(elements) {
+        Ok(json) => providers.push(ContentProvider::for_bytes(
+            MIME_TYPE,
+            &Bytes::from_owned(json),
+        )),
+        Err(err) => log::warn!("Failed to copy elements: {}", err),
+    }
+    if !elements.is_empty() {
+        // render the elements on a page of their own that fits them exactly
+        let selection = Selection {
+            page: 0,
+            layer: 0,
+            elements: elements.to_vec(),
+        };
+        let envelope = selection.envelope();
+        let (lower, upper) = (envelope.lower(), envelope.upper());
+        let moved = selection.transformed(&Transform2D::translation(-lower.x(), -lower.y()));
+        let mut page = Page::new(PaperSize::Custom(
+            upper.x() - lower.x(),
+            upper.y() - lower.y(),
+        ));
+        page.layers[0].elements = RTree::bulk_load(moved.elements);
+        let pages = vec![page];
+        let envelope = pages.envelope();
+        match export::svg_bytes(&pages, &envelope) {
+            Ok(svg) => providers.push(ContentProvider::for_bytes(
+                SVG_MIME_TYPE,
+                &Bytes::from_owned(svg),
+            )),
+            Err(err) => log::warn!("Failed to copy as SVG: {}", err),
+        }
+        match export::png_bytes(&pages, &envelope, PngOptions::default()) {
+            Ok(png) => providers.push(ContentProvider::for_bytes(
+                PNG_MIME_TYPE,
+                &Bytes::from_owned(png),
+            )),
+            Err(err) => log::warn!("Failed to copy as PNG: {}", err),
+        }
+    }
+    let provider = ContentProvider::new_union(&providers);
+    if let Err(err) = clipboard.set_content(Some(&provider)) {
+        log::warn!("Failed to set clipboard: {}", err);
+    }
+}
+
+/// read elements copied in this or another window and send them as `Action::Paste`, images copied
+/// elsewhere are sent as `Action::PasteImage`
+pub fn paste(clipboard: &Clipboard, sender: SyncSender<Action>) {
+    clipboard.read_async(
+        &[MIME_TYPE, PNG_MIME_TYPE, JPEG_MIME_TYPE],
+        PRIORITY_DEFAULT,
+        None::<&Cancellable>,
+        move |result| {
+            let (input, mime_type) = match result {
+                Ok(result) => result,
+                Err(err) => {
+                    log::warn!("Nothing to paste: {}", err);
+                    return;
+                }
+            };
+            let output = MemoryOutputStream::new_resizable();
+            let output_handle = output.clone();
+            output.splice_async(
+                &input,
+                OutputStreamSpliceFlags::CLOSE_SOURCE | OutputStreamSpliceFlags::CLOSE_TARGET,
+                PRIORITY_DEFAULT,
+                None::<&Cancellable>,
+                move |result| {
+                    if let Err(err) = result {
+                        log::warn!("Failed to read clipboard: {}", err);
+                        return;
+                    }
+                    let bytes = output_handle.steal_as_bytes();
+                    if mime_type == MIME_TYPE {
+                        match serde_json::from_slice::<Vec<Element>>(&bytes) {
+                            Ok(elements) => sender.send(Action::Paste(elements)).unwrap(),
+                            Err(err) => log::warn!("Failed to paste elements: {}", err),
+                        }
+                    } else {
+                        match Image::from_encoded(&bytes) {
+                            Ok(image) => sender.send(Action::PasteImage(image)).unwrap(),
+                            Err(err) => log::warn!("Failed to paste image: {}", err),
+                        }
+                    }
+                },
+            );
+        },
+    );
+}
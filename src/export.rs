@@ -0,0 +1,321 @@
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+
+use euclid::default::Transform2D;
+use geo::Point;
+use gtk::cairo::{self, Context, Format, ImageSurface, PdfSurface, SvgSurface};
+use rstar::{Envelope, AABB};
+use serde::{Deserialize, Serialize};
+
+use crate::canvas::{self, Layout};
+use crate::quadtree::{Document, Element, Page, Viewport};
+
+/// part of the document that gets exported
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportArea {
+    /// all pages
+    Document,
+    /// area currently visible on screen
+    Viewport,
+    /// bounding box of the selected elements, the whole document if nothing is selected
+    Selection,
+}
+
+/// size of a page
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaperSize {
+    A4,
+    Letter,
+    /// width and height in points
+    Custom(f64, f64),
+}
+
+impl PaperSize {
+    /// width and height in points
+    pub fn dimensions(self) -> (f64, f64) {
+        match self {
+            PaperSize::A4 => (595.275591, 841.889764),
+            PaperSize::Letter => (612.0, 792.0),
+            PaperSize::Custom(width, height) => (width, height),
+        }
+    }
+
+    /// named size matching `width` and `height` in points to within a point, custom otherwise
+    pub fn from_dimensions(width: f64, height: f64) -> Self {
+        [PaperSize::A4, PaperSize::Letter]
+            .iter()
+            .copied()
+            .find(|paper| {
+                let (w, h) = paper.dimensions();
+                (w - width).abs() < 1.0 && (h - height).abs() < 1.0
+            })
+            .unwrap_or(PaperSize::Custom(width, height))
+    }
+}
+
+/// document units per inch, documents are measured in points
+pub const UNITS_PER_INCH: f64 = 72.0;
+
+/// settings for raster export
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PngOptions {
+    pub dpi: f64,
+    /// leave the background transparent instead of painting it white
+    pub transparent: bool,
+}
+
+impl Default for PngOptions {
+    fn default() -> Self {
+        PngOptions {
+            dpi: 150.0,
+            transparent: false,
+        }
+    }
+}
+
+/// document space rectangle covered by `area`, `selection` is the envelope of the selection
+pub fn area_envelope(
+    pages: &[Page],
+    layout: Layout,
+    viewport: &Viewport,
+    selection: Option<AABB<Point<f64>>>,
+    area: ExportArea,
+) -> AABB<Point<f64>> {
+    match (area, selection) {
+        (ExportArea::Selection, Some(envelope)) => envelope,
+        (ExportArea::Viewport, _) => viewport.normalized(),
+        _ => document_envelope(pages, layout),
+    }
+}
+
+/// bounding box of everything on the pages, and of the pages themselves in paged layout, padded
+/// by half the widest stroke so lines along the edges are not cut off
+fn document_envelope(pages: &[Page], layout: Layout) -> AABB<Point<f64>> {
+    let mut envelope = match layout {
+        Layout::Paged => Some(pages.envelope()),
+        Layout::Canvas => None,
+    };
+    let mut margin = 0f64;
+    for (index, page) in pages.iter().enumerate() {
+        if let Some(content) = page.content_envelope() {
+            let (x, y) = pages.page_origin(index);
+            let (lower, upper) = (content.lower(), content.upper());
+            let content = AABB::from_corners(
+                (lower.x() + x, lower.y() + y).into(),
+                (upper.x() + x, upper.y() + y).into(),
+            );
+            envelope = Some(envelope.map_or(content, |envelope| envelope.merged(&content)));
+        }
+        for element in page.layers.iter().flat_map(|layer| layer.elements.iter()) {
+            if let Element::Stroke(ink) = element {
+                margin = margin.max(ink.style.width / 2.0);
+            }
+        }
+    }
+    // an empty canvas is exported as the size of one page
+    let envelope = envelope.unwrap_or_else(|| pages.envelope());
+    let (lower, upper) = (envelope.lower(), envelope.upper());
+    AABB::from_corners(
+        (lower.x() - margin, lower.y() - margin).into(),
+        (upper.x() + margin, upper.y() + margin).into(),
+    )
+}
+
+/// viewport that maps `envelope` onto an output of `scale` units per document unit
+pub fn envelope_viewport(envelope: &AABB<Point<f64>>, scale: f64) -> Viewport {
+    let lower = envelope.lower();
+    let upper = envelope.upper();
+    Viewport {
+        width: ((upper.x() - lower.x()) * scale).ceil() as i32,
+        height: ((upper.y() - lower.y()) * scale).ceil() as i32,
+        transform: Transform2D::scale(1.0 / scale, 1.0 / scale)
+            .then_translate((lower.x(), lower.y()).into()),
+    }
+}
+
+pub fn export_svg(
+    path: &Path,
+    pages: &[Page],
+    layout: Layout,
+    viewport: &Viewport,
+    selection: Option<AABB<Point<f64>>>,
+    area: ExportArea,
+) -> Result<(), cairo::Error> {
+    let envelope = area_envelope(pages, layout, viewport, selection, area);
+    let export_viewport = envelope_viewport(&envelope, 1.0);
+    let surface = SvgSurface::new(
+        export_viewport.width as f64,
+        export_viewport.height as f64,
+        Some(path),
+    )?;
+    let cairo_context = Context::new(&surface)?;
+    canvas::draw_paper(pages, layout, &cairo_context, &export_viewport);
+    pages.draw(&cairo_context, &export_viewport);
+    surface.finish();
+    Ok(())
+}
+
+/// SVG of everything inside `envelope` on pages in paged layout, kept in memory
+pub fn svg_bytes(pages: &[Page], envelope: &AABB<Point<f64>>) -> Result<Vec<u8>, cairo::IoError> {
+    let export_viewport = envelope_viewport(envelope, 1.0);
+    let surface = SvgSurface::for_stream(
+        export_viewport.width as f64,
+        export_viewport.height as f64,
+        Vec::<u8>::new(),
+    )?;
+    let cairo_context = Context::new(&surface)?;
+    pages.draw_paper(&cairo_context, &export_viewport);
+    pages.draw(&cairo_context, &export_viewport);
+    let stream = surface
+        .finish_output_stream()
+        .map_err(|err| cairo::IoError::Io(err.error))?;
+    Ok(*stream.downcast::<Vec<u8>>().unwrap())
+}
+
+/// export every page as a PDF page of the same size
+///
+/// `range` selects a range of page indices, `None` exports every page. A canvas has to be cut
+/// into pages first.
+pub fn export_pdf(
+    path: &Path,
+    pages: &[Page],
+    range: Option<Range<usize>>,
+) -> Result<(), cairo::Error> {
+    let range = range.unwrap_or(0..pages.len());
+    let range = range.start..range.end.min(pages.len());
+    let (width, height) = pages
+        .get(range.start)
+        .map_or(PaperSize::A4, |page| page.size)
+        .dimensions();
+    let surface = PdfSurface::new(width, height, path)?;
+    let cairo_context = Context::new(&surface)?;
+    for index in range {
+        let (width, height) = pages[index].size.dimensions();
+        surface.set_size(width, height)?;
+        let page_viewport = pages.page_viewport(
+            index,
+            &Viewport {
+                width: width.ceil() as i32,
+                height: height.ceil() as i32,
+                transform: Transform2D::identity(),
+            },
+        );
+        pages[index].draw_paper(&cairo_context, &page_viewport);
+        pages[index].draw(&cairo_context, &page_viewport);
+        cairo_context.show_page()?;
+    }
+    surface.finish();
+    Ok(())
+}
+
+pub fn export_png(
+    path: &Path,
+    pages: &[Page],
+    layout: Layout,
+    viewport: &Viewport,
+    selection: Option<AABB<Point<f64>>>,
+    area: ExportArea,
+    options: PngOptions,
+) -> Result<(), cairo::IoError> {
+    let envelope = area_envelope(pages, layout, viewport, selection, area);
+    let surface = render_png(pages, layout, &envelope, options)?;
+    let mut file = File::create(path).map_err(cairo::IoError::Io)?;
+    surface.write_to_png(&mut file)?;
+    Ok(())
+}
+
+/// PNG of everything inside `envelope` on pages in paged layout, kept in memory
+pub fn png_bytes(
+    pages: &[Page],
+    envelope: &AABB<Point<f64>>,
+    options: PngOptions,
+) -> Result<Vec<u8>, cairo::IoError> {
+    let surface = render_png(pages, Layout::Paged, envelope, options)?;
+    let mut bytes = Vec::new();
+    surface.write_to_png(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn render_png(
+    pages: &[Page],
+    layout: Layout,
+    envelope: &AABB<Point<f64>>,
+    options: PngOptions,
+) -> Result<ImageSurface, cairo::Error> {
+    let scale = options.dpi / UNITS_PER_INCH;
+    let export_viewport = envelope_viewport(envelope, scale);
+    let surface = ImageSurface::create(
+        Format::ARgb32,
+        export_viewport.width.max(1),
+        export_viewport.height.max(1),
+    )?;
+    let cairo_context = Context::new(&surface)?;
+    if !options.transparent {
+        cairo_context.set_source_rgb(1.0, 1.0, 1.0);
+        cairo_context.paint()?;
+    }
+    // draw at document scale and let cairo scale everything, line widths included, to the DPI
+    cairo_context.scale(scale, scale);
+    let scaled_viewport = Viewport {
+        transform: Transform2D::scale(scale, scale).then(&export_viewport.transform),
+        ..export_viewport
+    };
+    if !options.transparent {
+        canvas::draw_paper(pages, layout, &cairo_context, &scaled_viewport);
+    }
+    pages.draw(&cairo_context, &scaled_viewport);
+    Ok(surface)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poppler::PopplerDocument;
+
+    /// export `pages` to the temporary file `name` and read back the size of every page in it
+    fn exported_sizes(name: &str, pages: &[Page], range: Option<Range<usize>>) -> Vec<(f64, f64)> {
+        let path = std::env::temp_dir().join(format!("xournalr-{}-{}", std::process::id(), name));
+        export_pdf(&path, pages, range).unwrap();
+        let document = PopplerDocument::new_from_file(&path, "").unwrap();
+        let sizes = (0..document.get_n_pages())
+            .map(|index| document.get_page(index).unwrap().get_size())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        sizes
+    }
+
+    fn assert_sizes(actual: &[(f64, f64)], expected: &[PaperSize]) {
+        assert_eq!(actual.len(), expected.len());
+        for (&(width, height), size) in actual.iter().zip(expected) {
+            let (expected_width, expected_height) = size.dimensions();
+            assert!(
+                (width - expected_width).abs() < 0.01,
+                "{} != {}",
+                width,
+                expected_width
+            );
+            assert!(
+                (height - expected_height).abs() < 0.01,
+                "{} != {}",
+                height,
+                expected_height
+            );
+        }
+    }
+
+    #[test]
+    fn export_pdf_writes_every_page_in_its_size() {
+        let pages = vec![Page::new(PaperSize::A4), Page::new(PaperSize::Letter)];
+        let sizes = exported_sizes("all.pdf", &pages, None);
+        assert_sizes(&sizes, &[PaperSize::A4, PaperSize::Letter]);
+    }
+
+    #[test]
+    fn export_pdf_writes_only_the_range() {
+        let pages = vec![Page::new(PaperSize::A4), Page::new(PaperSize::Letter)];
+        let sizes = exported_sizes("range.pdf", &pages, Some(1..2));
+        assert_sizes(&sizes, &[PaperSize::Letter]);
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::VecDeque;
+
+use crate::background::Background;
+use crate::canvas::Layout;
+use crate::export::PaperSize;
+use crate::quadtree::{Element, Layer, Page};
+
+/// number of undo steps kept by default
+pub const DEFAULT_HISTORY_DEPTH: usize = 100;
+
+/// single invertible document mutation, elements are addressed by the index of their page
+/// followed by the index of their layer on that page
+#[derive(Clone, Debug)]
+pub enum Change {
+    Insert(usize, usize, Element),
+    Remove(usize, usize, Element),
+    InsertPage(usize, Page),
+    RemovePage(usize, Page),
+    /// move the page at the first index so it ends up at the second
+    MovePage(usize, usize),
+    /// change the size of a page from the first to the second size
+    ResizePage(usize, PaperSize, PaperSize),
+    /// change the background of a page from the first to the second one
+    SetBackground(usize, Background, Background),
+    InsertLayer(usize, usize, Layer),
+    RemoveLayer(usize, usize, Layer),
+    /// move a layer of the page at the first index from the second index to the third
+    MoveLayer(usize, usize, usize),
+    /// replace all pages in the first layout by the pages in the second layout
+    SetLayout(Layout, Vec<Page>, Layout, Vec<Page>),
+}
+
+impl Change {
+    fn inverse(&self) -> Change {
+        match self {
+            Change::Insert(page, layer, element) => Change::Remove(*page, *layer, element.clone()),
+            Change::Remove(page, layer, element) => Change::Insert(*page, *layer, element.clone()),
+            Change::InsertPage(index, page) => Change::RemovePage(*index, page.clone()),
+            Change::RemovePage(index, page) => Change::InsertPage(*index, page.clone()),
+            Change::MovePage(from, to) => Change::MovePage(*to, *from),
+            Change::ResizePage(index, from, to) => Change::ResizePage(*index, *to, *from),
+            Change::SetBackground(index, from, to) => Change::SetBackground(*index, *to, *from),
+            Change::InsertLayer(page, index, layer) => {
+                Change::RemoveLayer(*page, *index, layer.clone())
+            }
+            Change::RemoveLayer(page, index, layer) => {
+                Change::InsertLayer(*page, *index, layer.clone())
+            }
+            Change::MoveLayer(page, from, to) => Change::MoveLayer(*page, *to, *from),
+            Change::SetLayout(from, from_pages, to, to_pages) => {
+                Change::SetLayout(*to, to_pages.clone(), *from, from_pages.clone())
+            }
+        }
+    }
+
+    pub fn apply(&self, pages: &mut Vec<Page>, layout: &mut Layout) {
+        let in_range = match self {
+            Change::InsertPage(index, _) => *index <= pages.len(),
+            Change::MovePage(from, to) => *from < pages.len() && *to < pages.len(),
+            Change::RemovePage(index, _)
+            | Change::ResizePage(index, ..)
+            | Change::SetBackground(index, ..) => *index < pages.len(),
+            Change::Insert(page, layer, _)
+            | Change::Remove(page, layer, _)
+            | Change::RemoveLayer(page, layer, _) => {
+                pages.get(*page).map_or(false, |p| *layer < p.layers.len())
+            }
+            Change::InsertLayer(page, layer, _) => {
+                pages.get(*page).map_or(false, |p| *layer <= p.layers.len())
+            }
+            Change::MoveLayer(page, from, to) => pages
+                .get(*page)
+                .map_or(false, |p| *from < p.layers.len() && *to < p.layers.len()),
+            Change::SetLayout(..) => true,
+        };
+        if !in_range {
+            log::warn!("History out of sync, page or layer not found");
+            return;
+        }
+        match self {
+            Change::Insert(page, layer, element) => {
+                pages[*page].layers[*layer].elements.insert(element.clone())
+            }
+            Change::Remove(page, layer, element) => {
+                if pages[*page].layers[*layer]
+                    .elements
+                    .remove(element)
+                    .is_none()
+                {
+                    log::warn!("History out of sync, element to remove not found");
+                }
+            }
+            Change::InsertPage(index, page) => pages.insert(*index, page.clone()),
+            Change::RemovePage(index, _) => {
+                pages.remove(*index);
+            }
+            Change::MovePage(from, to) => {
+                let page = pages.remove(*from);
+                pages.insert(*to, page);
+            }
+            Change::ResizePage(index, _, size) => pages[*index].size = *size,
+            Change::SetBackground(index, _, background) => pages[*index].background = *background,
+            Change::InsertLayer(page, index, layer) => {
+                pages[*page].layers.insert(*index, layer.clone())
+            }
+            Change::RemoveLayer(page, index, _) => {
+                let page = &mut pages[*page];
+                page.layers.remove(*index);
+                // the active layer has to stay in range
+                page.layer = page.layer.min(page.layers.len().saturating_sub(1));
+            }
+            Change::MoveLayer(page, from, to) => {
+                let layers = &mut pages[*page].layers;
+                let layer = layers.remove(*from);
+                layers.insert(*to, layer);
+            }
+            Change::SetLayout(_, _, to, to_pages) => {
+                *pages = to_pages.clone();
+                *layout = *to;
+            }
+        }
+    }
+}
+
+/// undo and redo stacks, each entry is one user visible step
+#[derive(Clone)]
+pub struct History {
+    undo: VecDeque<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+    /// maximum number of undo steps
+    depth: usize,
+}
+
+impl History {
+    pub fn new(depth: usize) -> Self {
+        History {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            depth,
+        }
+    }
+
+    /// change the maximum number of undo steps, dropping the oldest ones that no longer fit
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+        self.trim();
+    }
+
+    /// remember already applied changes as one step
+    pub fn record(&mut self, changes: Vec<Change>) {
+        if changes.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push_back(changes);
+        self.trim();
+    }
+
+    fn trim(&mut self) {
+        while self.undo.len() > self.depth {
+            self.undo.pop_front();
+        }
+    }
+
+    pub fn undo(&mut self, pages: &mut Vec<Page>, layout: &mut Layout) {
+        if let Some(changes) = self.undo.pop_back() {
+            for change in changes.iter().rev() {
+                change.inverse().apply(pages, layout);
+            }
+            self.redo.push(changes);
+        }
+    }
+
+    pub fn redo(&mut self, pages: &mut Vec<Page>, layout: &mut Layout) {
+        if let Some(changes) = self.redo.pop() {
+            for change in &changes {
+                change.apply(pages, layout);
+            }
+            self.undo.push_back(changes);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::quadtree::{Ink, Stroke, StrokeStyle};
+
+    fn stroke() -> Element {
+        let mut ink = Ink::new(StrokeStyle::default());
+        ink.add(0.0, 0.0, None);
+        ink.add(10.0, 5.0, None);
+        Element::Stroke(ink)
+    }
+
+    fn sizes(pages: &[Page]) -> Vec<PaperSize> {
+        pages.iter().map(|page| page.size).collect()
+    }
+
+    /// apply `change` to `pages` and record it as one step
+    fn change(history: &mut History, pages: &mut Vec<Page>, change: Change) {
+        change.apply(pages, &mut Layout::Paged);
+        history.record(vec![change]);
+    }
+
+    #[test]
+    fn undo_and_redo_restore_the_pages() {
+        let mut pages = vec![Page::new(PaperSize::A4)];
+        let mut layout = Layout::Paged;
+        let mut history = History::new(DEFAULT_HISTORY_DEPTH);
+        change(&mut history, &mut pages, Change::Insert(0, 0, stroke()));
+        change(
+            &mut history,
+            &mut pages,
+            Change::InsertPage(1, Page::new(PaperSize::Letter)),
+        );
+
+        history.undo(&mut pages, &mut layout);
+        assert_eq!(sizes(&pages), vec![PaperSize::A4]);
+        assert_eq!(pages[0].layers[0].elements.size(), 1);
+        history.undo(&mut pages, &mut layout);
+        assert_eq!(pages[0].layers[0].elements.size(), 0);
+        // nothing left to undo
+        history.undo(&mut pages, &mut layout);
+        assert_eq!(sizes(&pages), vec![PaperSize::A4]);
+
+        history.redo(&mut pages, &mut layout);
+        assert_eq!(pages[0].layers[0].elements.size(), 1);
+        history.redo(&mut pages, &mut layout);
+        assert_eq!(sizes(&pages), vec![PaperSize::A4, PaperSize::Letter]);
+    }
+
+    #[test]
+    fn undo_reverts_the_changes_of_a_step_in_reverse_order() {
+        let mut pages = vec![Page::new(PaperSize::A4)];
+        let mut layout = Layout::Paged;
+        let mut history = History::new(DEFAULT_HISTORY_DEPTH);
+        let changes = vec![
+            Change::InsertPage(1, Page::new(PaperSize::Letter)),
+            Change::MovePage(1, 0),
+            Change::ResizePage(0, PaperSize::Letter, PaperSize::Custom(100.0, 200.0)),
+        ];
+        for change in &changes {
+            change.apply(&mut pages, &mut layout);
+        }
+        history.record(changes);
+        assert_eq!(
+            sizes(&pages),
+            vec![PaperSize::Custom(100.0, 200.0), PaperSize::A4]
+        );
+
+        history.undo(&mut pages, &mut layout);
+        assert_eq!(sizes(&pages), vec![PaperSize::A4]);
+    }
+
+    #[test]
+    fn inverse_changes_undo_the_change() {
+        let mut pages = vec![Page::new(PaperSize::A4), Page::new(PaperSize::Letter)];
+        let mut layout = Layout::Paged;
+        let changes = vec![
+            Change::Insert(1, 0, stroke()),
+            Change::MovePage(0, 1),
+            Change::ResizePage(0, PaperSize::A4, PaperSize::Letter),
+            Change::InsertLayer(1, 1, Layer::new(Layer::default_name(1))),
+            Change::MoveLayer(1, 0, 1),
+            Change::SetLayout(
+                Layout::Paged,
+                pages.clone(),
+                Layout::Canvas,
+                vec![Page::new(PaperSize::A4)],
+            ),
+        ];
+        for change in &changes {
+            let before = serde_json::to_value(&pages).unwrap();
+            change.apply(&mut pages, &mut layout);
+            change.inverse().apply(&mut pages, &mut layout);
+            assert_eq!(
+                serde_json::to_value(&pages).unwrap(),
+                before,
+                "{:?}",
+                change
+            );
+            assert_eq!(layout, Layout::Paged);
+        }
+    }
+
+    #[test]
+    fn new_changes_clear_redo() {
+        let mut pages = vec![Page::new(PaperSize::A4)];
+        let mut layout = Layout::Paged;
+        let mut history = History::new(DEFAULT_HISTORY_DEPTH);
+        change(
+            &mut history,
+            &mut pages,
+            Change::ResizePage(0, PaperSize::A4, PaperSize::Letter),
+        );
+        history.undo(&mut pages, &mut layout);
+        change(
+            &mut history,
+            &mut pages,
+            Change::ResizePage(0, PaperSize::A4, PaperSize::Custom(100.0, 100.0)),
+        );
+
+        history.redo(&mut pages, &mut layout);
+        assert_eq!(sizes(&pages), vec![PaperSize::Custom(100.0, 100.0)]);
+    }
+
+    #[test]
+    fn only_the_newest_steps_within_depth_are_kept() {
+        let mut pages = vec![Page::new(PaperSize::A4)];
+        let mut layout = Layout::Paged;
+        let mut history = History::new(3);
+        for index in 1..=4 {
+            let size = PaperSize::Custom(index as f64, index as f64);
+            let previous = pages[0].size;
+            change(
+                &mut history,
+                &mut pages,
+                Change::ResizePage(0, previous, size),
+            );
+        }
+        for _ in 0..4 {
+            history.undo(&mut pages, &mut layout);
+        }
+        assert_eq!(sizes(&pages), vec![PaperSize::Custom(1.0, 1.0)]);
+
+        // lowering the depth drops the oldest steps that are still there
+        for _ in 0..3 {
+            history.redo(&mut pages, &mut layout);
+        }
+        history.set_depth(1);
+        for _ in 0..3 {
+            history.undo(&mut pages, &mut layout);
+        }
+        assert_eq!(sizes(&pages), vec![PaperSize::Custom(3.0, 3.0)]);
+    }
+}
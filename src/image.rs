@@ -0,0 +1,167 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::fs;
+use std::io::{self, Cursor};
+use std::path::Path;
+use std::sync::{Arc, Weak};
+
+use geo::Point;
+use gtk::cairo::{Context, ImageSurface};
+use gtk::gdk_pixbuf::Pixbuf;
+use gtk::gio::{Cancellable, MemoryInputStream};
+use gtk::glib::{self, Bytes};
+use rstar::AABB;
+use serde::{Deserialize, Serialize};
+
+use crate::export::UNITS_PER_INCH;
+use crate::quadtree::Viewport;
+
+/// resolution images are placed at, so they appear at the size other applications show them
+pub const PIXELS_PER_INCH: f64 = 96.0;
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Decode(glib::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Decode(err) => write!(f, "invalid image: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<glib::Error> for Error {
+    fn from(err: glib::Error) -> Self {
+        Error::Decode(err)
+    }
+}
+
+/// raster image as stored in the document
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Image {
+    /// top left corner in document coordinates
+    pub position: (f64, f64),
+    /// width and height in document units
+    pub size: (f64, f64),
+    /// PNG encoded pixels, shared between copies of the image
+    #[serde(with = "base64")]
+    pub data: Arc<[u8]>,
+}
+
+impl Image {
+    /// decode a PNG, JPEG or any other format GdkPixbuf understands, stored as PNG
+    pub fn from_encoded(bytes: &[u8]) -> Result<Self, Error> {
+        let stream = MemoryInputStream::from_bytes(&Bytes::from(bytes));
+        let pixbuf = Pixbuf::from_stream(&stream, None::<&Cancellable>)?;
+        let data = if bytes.starts_with(b"\x89PNG") {
+            bytes.to_vec()
+        } else {
+            pixbuf.save_to_bufferv("png", &[])?
+        };
+        let scale = UNITS_PER_INCH / PIXELS_PER_INCH;
+        Ok(Image {
+            position: (0.0, 0.0),
+            size: (
+                pixbuf.width() as f64 * scale,
+                pixbuf.height() as f64 * scale,
+            ),
+            data: data.into(),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::from_encoded(&fs::read(path)?)
+    }
+
+    /// shrink the image, keeping its aspect ratio, until it fits into `size`
+    pub fn fit(&mut self, size: (f64, f64)) {
+        let scale = (size.0 / self.size.0).min(size.1 / self.size.1);
+        if scale < 1.0 {
+            self.size = (self.size.0 * scale, self.size.1 * scale);
+        }
+    }
+
+    pub fn envelope(&self) -> AABB<Point<f64>> {
+        let (x, y) = self.position;
+        AABB::from_corners((x, y).into(), (x + self.size.0, y + self.size.1).into())
+    }
+
+    pub fn draw(&self, cairo_context: &Context, viewport: &Viewport) {
+        let surface = match surface(&self.data) {
+            Some(surface) if surface.width() > 0 && surface.height() > 0 => surface,
+            _ => return,
+        };
+        let scale = viewport.transform.m11;
+        let (x, y) = viewport.transform_to_viewport(self.position);
+        cairo_context.save().unwrap();
+        cairo_context.translate(x, y);
+        cairo_context.scale(
+            self.size.0 / scale / surface.width() as f64,
+            self.size.1 / scale / surface.height() as f64,
+        );
+        cairo_context
+            .set_source_surface(&surface, 0.0, 0.0)
+            .unwrap();
+        cairo_context.paint().unwrap();
+        cairo_context.restore().unwrap();
+    }
+}
+
+thread_local! {
+    /// decoded images, kept as long as any copy of their data is alive
+    static SURFACES: RefCell<Vec<(Weak<[u8]>, ImageSurface)>> = RefCell::new(Vec::new());
+}
+
+/// decoded pixels of PNG `data`
+fn surface(data: &Arc<[u8]>) -> Option<ImageSurface> {
+    SURFACES.with(|surfaces| {
+        let mut surfaces = surfaces.borrow_mut();
+        let cached = surfaces.iter().find(|(weak, _)| {
+            weak.upgrade()
+                .map_or(false, |cached| Arc::ptr_eq(&cached, data))
+        });
+        if let Some((_, surface)) = cached {
+            return Some(surface.clone());
+        }
+        surfaces.retain(|(weak, _)| weak.strong_count() > 0);
+        match ImageSurface::create_from_png(&mut Cursor::new(&data[..])) {
+            Ok(surface) => {
+                surfaces.push((Arc::downgrade(data), surface.clone()));
+                Some(surface)
+            }
+            Err(err) => {
+                log::warn!("Failed to decode image: {}", err);
+                None
+            }
+        }
+    })
+}
+
+/// image data as a base64 string instead of an array of numbers
+mod base64 {
+    use std::sync::Arc;
+
+    use gtk::glib;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &Arc<[u8]>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&glib::base64_encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Arc<[u8]>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Ok(glib::base64_decode(&text).into())
+    }
+}
@@ -0,0 +1,135 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+use gtk::glib::SyncSender;
+use gtk::prelude::*;
+use gtk::{Align, Button, CheckButton, Entry, ListBox, ListBoxRow, Orientation, ToggleButton};
+
+use crate::logic::Action;
+use crate::quadtree::{Layer, Page};
+
+/// buttons below the list as (icon, tooltip, action)
+const BUTTONS: &[(&str, &str, &str)] = &[
+    ("list-add-symbolic", "Add Layer", "app.add_layer"),
+    ("list-remove-symbolic", "Delete Layer", "app.delete_layer"),
+    ("go-up-symbolic", "Move Layer Up", "app.move_layer_up"),
+    ("go-down-symbolic", "Move Layer Down", "app.move_layer_down"),
+    (
+        "go-bottom-symbolic",
+        "Merge Layer Down",
+        "app.merge_layer_down",
+    ),
+];
+
+/// layers of the current page, top layer first, with buttons to change the stack
+#[derive(Clone)]
+pub struct LayerPanel {
+    pub widget: gtk::Box,
+    list: ListBox,
+    sender: SyncSender<Action>,
+    /// number of layers listed, rows count from the top layer down
+    count: Rc<Cell<usize>>,
+    /// page, active layer and (name, visible, locked) of every layer as currently listed
+    shown: Option<(usize, usize, Vec<(String, bool, bool)>)>,
+}
+
+impl LayerPanel {
+    pub fn new(sender: SyncSender<Action>) -> Self {
+        let list = ListBox::new();
+        let count = Rc::new(Cell::new(0));
+        let row_sender = sender.clone();
+        let row_count = count.clone();
+        list.connect_row_activated(move |_, row| {
+            let index = row_count.get().saturating_sub(row.index() as usize + 1);
+            row_sender.send(Action::SelectLayer(index)).unwrap();
+        });
+        let buttons = gtk::Box::new(Orientation::Horizontal, 0);
+        for (icon, tooltip, action) in BUTTONS {
+            let button = Button::from_icon_name(Some(icon));
+            button.set_tooltip_text(Some(tooltip));
+            button.set_action_name(Some(action));
+            buttons.append(&button);
+        }
+        let widget = gtk::Box::new(Orientation::Vertical, 6);
+        widget.add_css_class("background");
+        widget.set_halign(Align::End);
+        widget.set_valign(Align::Start);
+        widget.set_margin_top(6);
+        widget.set_margin_end(6);
+        widget.append(&list);
+        widget.append(&buttons);
+        widget.hide();
+        LayerPanel {
+            widget,
+            list,
+            sender,
+            count,
+            shown: None,
+        }
+    }
+
+    /// list the layers of `page`, which has index `index`, unless they are listed already
+    pub fn update(&mut self, index: usize, page: &Page) {
+        let shown = (
+            index,
+            page.layer,
+            page.layers
+                .iter()
+                .map(|layer| (layer.name.clone(), layer.visible, layer.locked))
+                .collect::<Vec<_>>(),
+        );
+        if self.shown.as_ref() == Some(&shown) {
+            return;
+        }
+        while let Some(child) = self.list.first_child() {
+            self.list.remove(&child);
+        }
+        for (index, layer) in page.layers.iter().enumerate().rev() {
+            let row = self.row(index, layer);
+            self.list.append(&row);
+            if index == page.layer {
+                self.list.select_row(Some(&row));
+            }
+        }
+        self.count.set(page.layers.len());
+        self.shown = Some(shown);
+    }
+
+    /// visibility and lock toggles followed by the editable name of layer `index`
+    fn row(&self, index: usize, layer: &Layer) -> ListBoxRow {
+        let visible = CheckButton::new();
+        visible.set_tooltip_text(Some("Visible"));
+        visible.set_active(layer.visible);
+        let sender = self.sender.clone();
+        visible.connect_toggled(move |button| {
+            sender
+                .send(Action::LayerVisible(index, button.is_active()))
+                .unwrap();
+        });
+        let locked = ToggleButton::new();
+        locked.set_icon_name("changes-prevent-symbolic");
+        locked.set_tooltip_text(Some("Locked"));
+        locked.set_active(layer.locked);
+        let sender = self.sender.clone();
+        locked.connect_toggled(move |button| {
+            sender
+                .send(Action::LayerLocked(index, button.is_active()))
+                .unwrap();
+        });
+        let name = Entry::new();
+        name.set_text(&layer.name);
+        let sender = self.sender.clone();
+        name.connect_activate(move |entry| {
+            sender
+                .send(Action::RenameLayer(index, entry.text().to_string()))
+                .unwrap();
+        });
+        let content = gtk::Box::new(Orientation::Horizontal, 6);
+        content.append(&visible);
+        content.append(&locked);
+        content.append(&name);
+        let row = ListBoxRow::new();
+        row.set_child(Some(&content));
+        row
+    }
+}
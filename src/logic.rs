@@ -1,31 +1,130 @@
-use geo::{algorithm::intersects::Intersects, LineString};
+use euclid::default::Transform2D;
+use geo::Point;
 use gtk::{
     cairo::{LineCap, LineJoin},
+    gio::SimpleAction,
     graphene::Rect,
     gsk::{CairoNode, IsRenderNode, RenderNode},
-    prelude::WidgetExt,
+    prelude::*,
+    CssProvider, TextView,
 };
 use ring_channel::RingSender;
-use rstar::{RTree, RTreeObject};
+use rstar::{Envelope, RTreeObject, AABB};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
 
+use crate::background::{Background, Pattern};
+use crate::canvas::{self, Layout};
 use crate::custom_widget::MainWidget;
-use crate::quadtree::{Document, Stroke, Viewport};
+use crate::export::{self, ExportArea, PaperSize, PngOptions};
+use crate::history::{Change, History};
+use crate::image::Image;
+use crate::layer_panel::LayerPanel;
+use crate::quadtree::{
+    Document, Element, Ink, Layer, Page, Rgba, Stroke, StrokeKind, StrokeStyle, Viewport, PAGE_GAP,
+};
+use crate::recognizer;
+use crate::selection::{Selection, SelectionKind, SelectionTransform};
+use crate::shapes::{Shape, ShapeDrag, ShapeModifiers};
+use crate::storage::{self, DocumentFile};
+use crate::text::{TextBox, TextEdit, TextStyle};
+use crate::{pdf, xoj, xopp};
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub enum Action {
     MousePress(MousePressAction),
     MouseMotion(MouseMotionAction),
+    /// motion events a stylus reported at once, handled in order
+    MouseMotions(Vec<MouseMotionAction>),
     MouseRelease(MouseReleaseAction),
     Allocation(AllocationAction),
     Zoom(ZoomEvent),
+    /// zoom in one step around the centre of the viewport
+    ZoomIn,
+    ZoomOut,
+    ZoomPreset(ZoomPreset),
     Scroll(ScrollEvent),
     Motion(MotionEvent),
     ToolPen,
+    ToolHighlighter,
     ToolEraser,
     ToolObjEraser,
     ToolHand,
+    ToolShape(Shape),
+    ToolText,
+    ToolSelect(SelectionKind),
+    /// content of the text box being edited changed
+    TextInput(String),
+    /// stop editing the current text box
+    TextCommit,
+    /// Pango font description without a size and the size in document units for new text
+    TextFont(String, f64),
+    ShapeModifiers(ShapeModifiers),
+    ShapeRecognition(bool),
+    RecognizerTolerance(f64),
+    EraserRadius(f64),
+    PenColor(Rgba),
+    /// pen width in document units
+    PenWidth(f64),
+    KineticScrolling(bool),
+    KineticScroll,
     ScrollStart,
     ScrollEnd,
+    Open(PathBuf),
+    Save,
+    SaveAs(PathBuf),
+    Import(PathBuf),
+    ExportXopp(PathBuf),
+    ExportSvg(PathBuf, ExportArea),
+    /// export the pages in the range of page indices, or every page for `None`
+    ExportPdf(PathBuf, Option<Range<usize>>),
+    ExportPng(PathBuf, ExportArea),
+    PngDpi(f64),
+    PngTransparent(bool),
+    Undo,
+    Redo,
+    /// maximum number of undo steps
+    HistoryDepth(usize),
+    /// put the selection on the clipboard
+    Copy,
+    /// put the selection on the clipboard and remove it from the document
+    Cut,
+    /// elements read from the clipboard, placed at the pointer
+    Paste(Vec<Element>),
+    /// image read from the clipboard, placed at the pointer
+    PasteImage(Image),
+    InsertImage(PathBuf),
+    /// insert a blank page of the same size before the current page
+    InsertPageBefore,
+    /// insert a blank page of the same size after the current page
+    InsertPageAfter,
+    DuplicatePage,
+    DeletePage,
+    MovePageUp,
+    MovePageDown,
+    PageSize(PaperSize),
+    /// switch between pages and an infinite canvas, slicing the canvas into pages or joining the
+    /// pages onto a canvas
+    Layout(Layout),
+    BackgroundPattern(Pattern),
+    /// distance between the lines of the background pattern in document units
+    BackgroundSpacing(f64),
+    BackgroundColor(Rgba),
+    /// add a layer above the active layer of the current page
+    AddLayer,
+    DeleteLayer,
+    MoveLayerUp,
+    MoveLayerDown,
+    /// move the elements of the active layer onto the layer below it
+    MergeLayerDown,
+    /// make the layer with this index on the current page the active one
+    SelectLayer(usize),
+    LayerVisible(usize, bool),
+    LayerLocked(usize, bool),
+    RenameLayer(usize, String),
 }
 
 #[derive(Clone, Copy)]
@@ -36,9 +135,20 @@ pub struct MotionEvent {
 
 #[derive(Clone, Copy)]
 pub struct ZoomEvent {
+    /// scroll steps, positive values zoom out
     pub dscale: f64,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZoomPreset {
+    /// the whole current page, or the content of an infinite canvas
+    FitPage,
+    /// the width of the current page
+    FitWidth,
+    /// one pixel per document unit
+    Actual,
+}
+
 #[derive(Clone, Copy)]
 pub struct AllocationAction {
     pub width: i32,
@@ -49,6 +159,8 @@ pub struct AllocationAction {
 pub struct MousePressAction {
     pub x: f64,
     pub y: f64,
+    /// stylus pressure, `None` for devices without pressure
+    pub pressure: Option<f64>,
 }
 
 #[derive(Clone, Copy)]
@@ -56,12 +168,16 @@ pub struct MousePressAction {
 pub struct MouseMotionAction {
     pub x: f64,
     pub y: f64,
+    /// stylus pressure, `None` for devices without pressure
+    pub pressure: Option<f64>,
 }
 
 #[derive(Clone, Copy)]
 pub struct MouseReleaseAction {
     pub x: f64,
     pub y: f64,
+    /// stylus pressure, `None` for devices without pressure
+    pub pressure: Option<f64>,
 }
 
 #[derive(Clone, Copy)]
@@ -74,34 +190,189 @@ pub struct ScrollEvent {
 pub struct Widgets {
     pub widget: MainWidget,
     pub pipeline: RingSender<RenderNode>,
+    /// input method aware editor shown on top of the text box being edited
+    pub text_view: TextView,
+    /// font of `text_view`, matching the edited text box at the current zoom
+    pub text_css: CssProvider,
+    /// position of the text box `text_view` is currently set up for
+    pub text_position: Option<(f64, f64)>,
+    /// scale of the viewport `text_css` was last set up for
+    pub text_scale: f64,
+    /// undo and clipboard actions, disabled while `text_view` is shown so it gets their shortcuts
+    pub edit_actions: Vec<SimpleAction>,
+    /// stateful action showing the tool, which opening a document changes as well
+    pub tool_action: SimpleAction,
+    /// stateful action showing the layout, which opening, importing and undo change as well
+    pub layout_action: SimpleAction,
+    pub layer_panel: LayerPanel,
 }
 
 #[derive(Clone, Default)]
 pub struct ScrollState {
     pub x_old: f64,
     pub y_old: f64,
+    /// pointer offsets with the time they were reached, oldest first, used for kinetic scrolling
+    pub samples: VecDeque<(Instant, (f64, f64))>,
+}
+
+impl ScrollState {
+    fn new(x: f64, y: f64) -> Self {
+        ScrollState {
+            x_old: x,
+            y_old: y,
+            samples: vec![(Instant::now(), (x, y))].into(),
+        }
+    }
+
+    /// remember that the pointer moved to `(x, y)`, forgetting what no longer affects the velocity
+    fn sample(&mut self, x: f64, y: f64) {
+        let now = Instant::now();
+        self.samples.push_back((now, (x, y)));
+        // keep one sample from before the window to measure against
+        while self.samples.len() > 2 && now - self.samples[1].0 > VELOCITY_WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    /// pan velocity in pixels per kinetic tick over the last `VELOCITY_WINDOW`, zero once the
+    /// pointer has rested for `VELOCITY_PAUSE`
+    fn velocity(&self) -> (f64, f64) {
+        let (&(start, (x0, y0)), &(end, (x1, y1))) =
+            match (self.samples.front(), self.samples.back()) {
+                (Some(first), Some(last)) => (first, last),
+                _ => return (0.0, 0.0),
+            };
+        let ticks = (end - start).as_secs_f64() / KINETIC_INTERVAL.as_secs_f64();
+        if end.elapsed() > VELOCITY_PAUSE || ticks <= 0.0 {
+            return (0.0, 0.0);
+        }
+        ((x1 - x0) / ticks, (y1 - y0) / ticks)
+    }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq)]
+/// span of pointer movement the kinetic velocity is measured over
+const VELOCITY_WINDOW: Duration = Duration::from_millis(100);
+/// a pointer resting this long before it is released does not start kinetic scrolling
+const VELOCITY_PAUSE: Duration = Duration::from_millis(50);
+/// interval between two kinetic scrolling steps
+pub const KINETIC_INTERVAL: Duration = Duration::from_millis(16);
+/// decay factor applied to the kinetic velocity on every tick
+pub const KINETIC_FRICTION: f64 = 0.92;
+/// kinetic scrolling stops once the velocity drops below this many pixels per tick
+pub const KINETIC_MIN_VELOCITY: f64 = 0.5;
+
+/// factor the zoom changes by per scroll step or zoom action
+const ZOOM_STEP: f64 = 1.1;
+/// zoom limits in pixels per document unit
+const MIN_ZOOM: f64 = 0.1;
+const MAX_ZOOM: f64 = 10.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Tool {
     Pen,
+    Highlighter,
     Eraser,
     ObjEraser,
-
-    #[allow(dead_code)]
     Hand,
+    Shape(Shape),
+    Text,
+    Select(SelectionKind),
+}
+
+impl Tool {
+    /// state of the `tool` action that selects this tool
+    pub fn action_state(self) -> &'static str {
+        match self {
+            Tool::Pen => "pen",
+            Tool::Highlighter => "highlighter",
+            Tool::Eraser => "eraser",
+            Tool::ObjEraser => "obj_eraser",
+            Tool::Hand => "hand",
+            Tool::Shape(Shape::Line) => "line",
+            Tool::Shape(Shape::Rectangle) => "rectangle",
+            Tool::Shape(Shape::Ellipse) => "ellipse",
+            Tool::Shape(Shape::Arrow) => "arrow",
+            Tool::Text => "text",
+            Tool::Select(SelectionKind::Rectangle) => "select_rectangle",
+            Tool::Select(SelectionKind::Lasso) => "select_lasso",
+        }
+    }
 }
 
+/// look of the object eraser path while it is dragged
+const OBJ_ERASER_STYLE: StrokeStyle = StrokeStyle {
+    kind: StrokeKind::Pen,
+    color: Rgba::new(1.0, 1.0, 1.0, 1.0),
+    width: 5.0,
+    opacity: 1.0,
+};
+
+/// look of the rectangle or lasso while a selection is dragged out
+const SELECTION_STYLE: StrokeStyle = StrokeStyle {
+    kind: StrokeKind::Pen,
+    color: Rgba::new(0.2, 0.4, 0.8, 1.0),
+    width: 1.0,
+    opacity: 1.0,
+};
+
+/// style of the highlighter until it is changed
+pub const DEFAULT_HIGHLIGHTER_STYLE: StrokeStyle = StrokeStyle {
+    kind: StrokeKind::Highlighter,
+    color: Rgba::new(1.0, 1.0, 0.0, 1.0),
+    width: 12.0,
+    opacity: 0.5,
+};
+
+/// default eraser radius in screen pixels
+pub const DEFAULT_ERASER_RADIUS: f64 = 5.0;
+
 #[derive(Clone)]
 pub struct AppState {
-    /// document
-    pub drawing: RTree<LineString<f64>>,
+    /// document, never empty
+    pub pages: Vec<Page>,
+    /// `pages` holds exactly one page on an infinite canvas
+    pub layout: Layout,
+    /// index of the page last drawn on, where page actions apply
+    pub page: usize,
     /// currently drawn stroke
-    pub stroke: Option<LineString<f64>>,
+    pub stroke: Option<Ink>,
+    /// style new strokes are drawn with
+    pub pen: StrokeStyle,
+    /// style new highlighter strokes are drawn with
+    pub highlighter: StrokeStyle,
     pub viewport: Viewport,
     pub scroll_state: Option<ScrollState>,
     pub pointer_old: Option<(f64, f64)>,
     pub tool: Tool,
+    /// shape being dragged out, previewed through `stroke`
+    pub shape_drag: Option<ShapeDrag>,
+    pub shape_modifiers: ShapeModifiers,
+    /// replace pen strokes resembling a shape by the clean shape
+    pub shape_recognition: bool,
+    /// see `recognizer::recognize`
+    pub recognizer_tolerance: f64,
+    /// font of new text, the colour is taken from `pen`
+    pub text_style: TextStyle,
+    pub text_edit: Option<TextEdit>,
+    pub selection: Option<Selection>,
+    /// drag moving, scaling or rotating `selection`, whose elements are taken out of the document
+    /// until it is released
+    pub selection_transform: Option<SelectionTransform>,
+    /// elements copied or cut by the last action, to be put on the clipboard by the UI
+    pub copied: Option<Vec<Element>>,
+    /// eraser radius in screen pixels
+    pub eraser_radius: f64,
+    /// continue panning after the hand tool is released
+    pub kinetic_scrolling: bool,
+    /// remaining kinetic velocity in pixels per tick
+    pub kinetic: Option<(f64, f64)>,
+    /// file the document was loaded from or last saved to
+    pub file: Option<PathBuf>,
+    pub png_options: PngOptions,
+    pub history: History,
+    /// changes made by the current pixel eraser drag, recorded as one step on release
+    pub erased: Vec<Change>,
 }
 
 impl Widgets {
@@ -114,126 +385,1094 @@ impl Widgets {
         );
         let cairo_node = CairoNode::new(&rect);
         let cairo_context = cairo_node.draw_context().unwrap();
-        cairo_context.set_source_rgb(255f64, 255f64, 255f64);
+        cairo_context.set_source_rgb(0.85, 0.85, 0.85);
         cairo_context.paint().unwrap();
-        cairo_context.set_source_rgb(0f64, 0f64, 255f64);
         cairo_context.set_line_join(LineJoin::Round);
         cairo_context.set_line_cap(LineCap::Round);
-        let elements = state.drawing.elements_in_viewport(&state.viewport);
-        for elem in elements {
-            elem.draw(&cairo_context, &state.viewport);
-        }
+        canvas::draw_paper(&state.pages, state.layout, &cairo_context, &state.viewport);
+        state.pages.draw(&cairo_context, &state.viewport);
         if let Some(stroke) = &state.stroke {
-            if state.tool == Tool::Eraser || state.tool == Tool::ObjEraser {
-                cairo_context.set_source_rgb(255f64, 255f64, 255f64);
-                cairo_context.set_line_width(5.0);
+            match state.tool {
+                Tool::Eraser => {
+                    if let Some(last) = stroke.line.0.last() {
+                        cairo_context.set_source_rgb(0.5, 0.5, 0.5);
+                        cairo_context.set_line_width(1.0);
+                        cairo_context.arc(
+                            last.x,
+                            last.y,
+                            state.eraser_radius,
+                            0.0,
+                            2.0 * std::f64::consts::PI,
+                        );
+                        cairo_context.stroke().unwrap();
+                    }
+                }
+                Tool::Select(_) => {
+                    cairo_context.set_dash(&[4.0, 4.0], 0.0);
+                    stroke.draw_direct(&cairo_context);
+                    cairo_context.set_dash(&[], 0.0);
+                }
+                _ => stroke.draw_direct(&cairo_context),
+            }
+        }
+        if let Some(selection) = &state.selection {
+            let page_viewport = state.pages.page_viewport(selection.page, &state.viewport);
+            match &state.selection_transform {
+                Some(transform) => {
+                    let preview = selection.transformed(&transform.transform);
+                    for element in &preview.elements {
+                        element.draw(&cairo_context, &page_viewport);
+                    }
+                    preview.draw(&cairo_context, &page_viewport);
+                }
+                None => selection.draw(&cairo_context, &page_viewport),
             }
-            stroke.draw_direct(&cairo_context);
         }
         self.pipeline.send(cairo_node.upcast()).unwrap();
         self.widget.queue_draw();
+        self.update_text_view(state);
+        self.layer_panel
+            .update(state.page, &state.pages[state.page]);
+        self.tool_action
+            .set_state(&state.tool.action_state().to_variant());
+        let layout = match state.layout {
+            Layout::Paged => "paged",
+            Layout::Canvas => "canvas",
+        };
+        self.layout_action.set_state(&layout.to_variant());
+    }
+
+    fn update_text_view(&mut self, state: &AppState) {
+        match &state.text_edit {
+            Some(edit) => {
+                let (x, y) = state
+                    .pages
+                    .page_viewport(edit.page, &state.viewport)
+                    .transform_to_viewport(edit.position);
+                self.text_view.set_margin_start(x.max(0.0) as i32);
+                self.text_view.set_margin_top(y.max(0.0) as i32);
+                let moved = self.text_position != Some(edit.position);
+                let scale = state.viewport.transform.m11;
+                if moved || self.text_scale != scale {
+                    self.text_scale = scale;
+                    let color = edit.style.color;
+                    let css = format!(
+                        "textview {{ {} }} \
+                         textview text {{ color: {}; background: transparent; }}",
+                        edit.style.css(scale),
+                        color.to_hex()
+                    );
+                    self.text_css.load_from_data(css.as_bytes());
+                }
+                if moved {
+                    self.text_position = Some(edit.position);
+                    self.text_view.buffer().set_text(&edit.text);
+                    self.text_view.show();
+                    self.text_view.grab_focus();
+                    for action in &self.edit_actions {
+                        action.set_enabled(false);
+                    }
+                }
+            }
+            None => {
+                if self.text_position.take().is_some() {
+                    self.text_view.hide();
+                    self.widget.grab_focus();
+                    for action in &self.edit_actions {
+                        action.set_enabled(true);
+                    }
+                }
+            }
+        }
     }
 }
 
 impl AppState {
     pub fn dispatch(&mut self, action: Action) {
+        if matches!(
+            action,
+            Action::Save
+                | Action::SaveAs(_)
+                | Action::ExportXopp(_)
+                | Action::ExportSvg(..)
+                | Action::ExportPdf(..)
+                | Action::ExportPng(..)
+                | Action::Undo
+                | Action::Redo
+                | Action::Paste(_)
+                | Action::PasteImage(_)
+                | Action::InsertImage(_)
+                | Action::InsertPageBefore
+                | Action::InsertPageAfter
+                | Action::DuplicatePage
+                | Action::DeletePage
+                | Action::MovePageUp
+                | Action::MovePageDown
+                | Action::PageSize(_)
+                | Action::Layout(_)
+                | Action::AddLayer
+                | Action::DeleteLayer
+                | Action::MoveLayerUp
+                | Action::MoveLayerDown
+                | Action::MergeLayerDown
+                | Action::LayerVisible(..)
+                | Action::LayerLocked(..)
+        ) {
+            // the document has to contain the text being typed
+            self.finish_text();
+        }
         match action {
-            Action::MousePress(MousePressAction { x, y }) => match self.tool {
-                Tool::Pen | Tool::Eraser | Tool::ObjEraser => {
-                    self.stroke = Some(LineString(Vec::new()));
-                    self.stroke.as_mut().unwrap().add(x, y);
+            Action::MousePress(MousePressAction { x, y, pressure }) => {
+                if !matches!(self.tool, Tool::Hand | Tool::Eraser | Tool::Select(_)) {
+                    self.page = self.page_at((x, y));
                 }
-                Tool::Hand => todo!(),
-            },
-            Action::MouseMotion(MouseMotionAction { x, y }) => match self.tool {
-                Tool::Pen | Tool::Eraser | Tool::ObjEraser => {
-                    self.stroke.as_mut().unwrap().add(x, y);
+                match self.tool {
+                    Tool::Pen | Tool::Highlighter => {
+                        let pen = if self.tool == Tool::Highlighter {
+                            self.highlighter
+                        } else {
+                            self.pen
+                        };
+                        // the stroke is drawn in viewport coordinates until it is normalized
+                        let style = StrokeStyle {
+                            width: pen.width / self.viewport.transform.m11,
+                            ..pen
+                        };
+                        self.stroke = Some(Ink::new(style));
+                        self.stroke.as_mut().unwrap().add(x, y, pressure);
+                    }
+                    Tool::ObjEraser => {
+                        self.stroke = Some(Ink::new(OBJ_ERASER_STYLE));
+                        self.stroke.as_mut().unwrap().add(x, y, pressure);
+                    }
+                    Tool::Eraser => {
+                        self.stroke = Some(Ink::new(StrokeStyle::default()));
+                        self.stroke.as_mut().unwrap().add(x, y, pressure);
+                        self.erase((x, y), (x, y));
+                    }
+                    Tool::Shape(shape) => {
+                        self.stroke = Some(Ink::new(StrokeStyle {
+                            width: self.pen.width / self.viewport.transform.m11,
+                            ..self.pen
+                        }));
+                        self.shape_drag = Some(ShapeDrag::new(shape, (x, y)));
+                        self.update_shape();
+                    }
+                    Tool::Text => {
+                        self.finish_text();
+                        self.start_text(self.page_viewport().normalize_from_viewport((x, y)));
+                    }
+                    Tool::Select(kind) => {
+                        let hit = self.selection.as_ref().and_then(|selection| {
+                            let page_viewport =
+                                self.pages.page_viewport(selection.page, &self.viewport);
+                            selection
+                                .hit(&page_viewport, (x, y))
+                                .map(|hit| (hit, page_viewport.normalize_from_viewport((x, y))))
+                        });
+                        if let Some((hit, start)) = hit {
+                            let selection = self.selection.as_ref().unwrap();
+                            let layer = &mut self.pages[selection.page].layers[selection.layer];
+                            for element in &selection.elements {
+                                layer.elements.remove(element);
+                            }
+                            self.selection_transform = Some(SelectionTransform::new(hit, start));
+                        } else {
+                            self.page = self.page_at((x, y));
+                            self.selection = None;
+                            self.stroke = Some(Ink::new(SELECTION_STYLE));
+                            self.stroke.as_mut().unwrap().add(x, y, None);
+                            if kind == SelectionKind::Rectangle {
+                                self.shape_drag = Some(ShapeDrag::new(Shape::Rectangle, (x, y)));
+                            }
+                        }
+                    }
+                    Tool::Hand => {
+                        self.kinetic = None;
+                        self.scroll_state = Some(ScrollState::new(x, y));
+                    }
+                }
+            }
+            Action::MouseMotions(motions) => {
+                for motion in motions {
+                    self.dispatch(Action::MouseMotion(motion));
+                }
+            }
+            Action::MouseMotion(MouseMotionAction { x, y, pressure }) => match self.tool {
+                Tool::Pen | Tool::Highlighter | Tool::ObjEraser => {
+                    self.stroke.as_mut().unwrap().add(x, y, pressure);
+                }
+                Tool::Eraser => {
+                    let stroke = self.stroke.as_mut().unwrap();
+                    let last = stroke.line.0.last().map(|c| (c.x, c.y)).unwrap_or((x, y));
+                    stroke.add(x, y, pressure);
+                    self.erase(last, (x, y));
                 }
-                Tool::Hand => todo!(),
+                Tool::Shape(_) => {
+                    if let Some(drag) = &mut self.shape_drag {
+                        drag.end = (x, y);
+                    }
+                    self.update_shape();
+                }
+                Tool::Text => (),
+                Tool::Select(_) if self.selection_transform.is_some() => {
+                    let pointer = self.selection_viewport().normalize_from_viewport((x, y));
+                    self.selection_transform.as_mut().unwrap().update(pointer);
+                }
+                Tool::Select(SelectionKind::Rectangle) => {
+                    if let Some(drag) = &mut self.shape_drag {
+                        drag.end = (x, y);
+                    }
+                    self.update_shape();
+                }
+                Tool::Select(SelectionKind::Lasso) => {
+                    self.stroke.as_mut().unwrap().add(x, y, None);
+                }
+                Tool::Hand => self.scroll(x, y),
             },
-            Action::MouseRelease(MouseReleaseAction { x, y }) => match self.tool {
-                Tool::Pen => {
+            Action::MouseRelease(MouseReleaseAction { x, y, pressure }) => match self.tool {
+                Tool::Pen | Tool::Highlighter => {
                     let mut stroke = self.stroke.take().unwrap();
-                    stroke.add(x, y);
-                    self.drawing.add(stroke, &self.viewport);
-                    self.stroke = None;
+                    stroke.add(x, y, pressure);
+                    if self.tool == Tool::Pen && self.shape_recognition {
+                        if let Some(line) =
+                            recognizer::recognize(&stroke.line, self.recognizer_tolerance)
+                        {
+                            stroke.line = line;
+                            stroke.pressure.clear();
+                        }
+                    }
+                    let stroke = stroke.normalize(&self.page_viewport());
+                    self.insert(Element::Stroke(stroke));
                 }
-                Tool::Eraser | Tool::ObjEraser => {
+                Tool::Eraser => {
+                    let stroke = self.stroke.take().unwrap();
+                    let last = stroke.line.0.last().map(|c| (c.x, c.y)).unwrap_or((x, y));
+                    self.erase(last, (x, y));
+                    let erased = std::mem::take(&mut self.erased);
+                    self.history.record(erased);
+                }
+                Tool::ObjEraser => {
                     let mut stroke = self.stroke.take().unwrap();
-                    stroke.add(x, y);
-                    let stroke = stroke.normalize(&self.viewport);
-                    let elements = self
-                        .drawing
-                        .drain_in_envelope_intersecting(stroke.envelope());
-                    if self.tool == Tool::Eraser {
-                        unimplemented!()
-                    } else {
-                        for e in elements
-                            .filter(|e| !stroke.intersects(e))
-                            .collect::<Vec<_>>()
-                        {
-                            self.drawing.insert(e);
+                    stroke.add(x, y, pressure);
+                    let stroke = stroke.normalize(&self.page_viewport());
+                    let page = self.page;
+                    let index = self.pages[page].layer;
+                    // only the active layer, and not at all while it is locked or hidden
+                    let layer = self.pages[page].active_layer_mut();
+                    if layer.editable() {
+                        let (removed, kept): (Vec<_>, Vec<_>) = layer
+                            .elements
+                            .drain_in_envelope_intersecting(stroke.envelope())
+                            .partition(|e| e.intersects(&stroke.line));
+                        for e in kept {
+                            layer.elements.insert(e);
                         }
+                        self.history.record(
+                            removed
+                                .into_iter()
+                                .map(|e| Change::Remove(page, index, e))
+                                .collect(),
+                        );
                     }
                     self.stroke = None;
                 }
-                Tool::Hand => todo!(),
+                Tool::Shape(_) => {
+                    if let Some(drag) = &mut self.shape_drag {
+                        drag.end = (x, y);
+                    }
+                    self.update_shape();
+                    self.shape_drag = None;
+                    let stroke = self.stroke.take().unwrap().normalize(&self.page_viewport());
+                    self.insert(Element::Stroke(stroke));
+                }
+                Tool::Text => (),
+                Tool::Select(_) if self.selection_transform.is_some() => {
+                    let pointer = self.selection_viewport().normalize_from_viewport((x, y));
+                    self.selection_transform.as_mut().unwrap().update(pointer);
+                    self.finish_selection_transform();
+                }
+                Tool::Select(kind) => {
+                    let mut stroke = self.stroke.take().unwrap();
+                    match kind {
+                        SelectionKind::Rectangle => {
+                            if let Some(drag) = &mut self.shape_drag {
+                                drag.end = (x, y);
+                                stroke.line = drag.outline(ShapeModifiers::default());
+                            }
+                            self.shape_drag = None;
+                        }
+                        SelectionKind::Lasso => stroke.add(x, y, None),
+                    }
+                    let mut line = stroke.normalize(&self.page_viewport()).line;
+                    self.selection = match kind {
+                        // locked and hidden layers can't be edited
+                        _ if !self.pages[self.page].active_layer().editable() => None,
+                        SelectionKind::Rectangle => {
+                            Selection::in_rectangle(&self.pages, self.page, &line.envelope())
+                        }
+                        SelectionKind::Lasso => {
+                            line.close();
+                            Selection::in_lasso(&self.pages, self.page, &line)
+                        }
+                    };
+                }
+                Tool::Hand => {
+                    self.scroll(x, y);
+                    if let Some(state) = self.scroll_state.take() {
+                        let (vx, vy) = state.velocity();
+                        if self.kinetic_scrolling && vx.hypot(vy) > KINETIC_MIN_VELOCITY {
+                            self.kinetic = Some((vx, vy));
+                        }
+                    }
+                }
             },
             Action::Allocation(AllocationAction { width, height }) => {
                 self.viewport.width = width;
                 self.viewport.height = height;
             }
             Action::ScrollStart => {
+                self.kinetic = None;
                 self.scroll_state = Some(ScrollState::default());
             }
-            Action::Scroll(ScrollEvent { dx, dy }) => {
-                let ddx;
-                let ddy;
-                if let Some(state) = &mut self.scroll_state {
-                    ddx = dx - state.x_old;
-                    ddy = dy - state.y_old;
-                    state.x_old = dx;
-                    state.y_old = dy;
-                } else {
-                    ddx = dx;
-                    ddy = dy;
-                }
-                self.viewport.transform.m31 -= ddx * self.viewport.transform.m11;
-                self.viewport.transform.m32 -= ddy * self.viewport.transform.m11;
-            }
+            Action::Scroll(ScrollEvent { dx, dy }) => self.scroll(dx, dy),
             Action::ScrollEnd => {
                 self.scroll_state = None;
             }
             Action::ToolPen => {
-                self.tool = Tool::Pen;
+                self.set_tool(Tool::Pen);
+            }
+            Action::ToolHighlighter => {
+                self.set_tool(Tool::Highlighter);
             }
             Action::ToolEraser => {
-                self.tool = Tool::Eraser;
+                self.set_tool(Tool::Eraser);
             }
             Action::ToolObjEraser => {
-                self.tool = Tool::ObjEraser;
+                self.set_tool(Tool::ObjEraser);
             }
-            Action::ToolHand => todo!(),
-            Action::Zoom(ZoomEvent { dscale }) => {
-                let dscale = dscale / 10f64;
-                let mut dx = 0f64;
-                let mut dy = 0f64;
-                let scale_y = self.viewport.transform.m22 + dscale;
-                let scale_x = self.viewport.transform.m11 + dscale;
-                if let Some((x, y)) = self.pointer_old {
-                    dx = x * dscale;
-                    dy = y * dscale;
-                }
-                if scale_y > 0f64 && scale_x > 0f64 {
-                    self.viewport.transform.m22 = scale_y;
-                    self.viewport.transform.m11 = scale_x;
-                    self.viewport.transform.m31 -= dx;
-                    self.viewport.transform.m32 -= dy;
+            Action::ToolHand => {
+                self.set_tool(Tool::Hand);
+            }
+            Action::ToolShape(shape) => {
+                self.set_tool(Tool::Shape(shape));
+            }
+            Action::ToolText => {
+                self.set_tool(Tool::Text);
+            }
+            Action::ToolSelect(kind) => {
+                self.set_tool(Tool::Select(kind));
+            }
+            Action::TextInput(text) => {
+                if let Some(edit) = &mut self.text_edit {
+                    edit.text = text;
+                }
+            }
+            Action::TextCommit => self.finish_text(),
+            Action::TextFont(font, size) => {
+                self.text_style.font = font;
+                self.text_style.size = size;
+            }
+            Action::ShapeModifiers(modifiers) => {
+                self.shape_modifiers = modifiers;
+                self.update_shape();
+            }
+            Action::ShapeRecognition(enabled) => {
+                self.shape_recognition = enabled;
+            }
+            Action::RecognizerTolerance(tolerance) => {
+                self.recognizer_tolerance = tolerance;
+            }
+            Action::EraserRadius(radius) => {
+                self.eraser_radius = radius;
+            }
+            Action::PenColor(color) => {
+                self.active_style().color = color;
+            }
+            Action::PenWidth(width) => {
+                self.active_style().width = width;
+            }
+            Action::KineticScrolling(enabled) => {
+                self.kinetic_scrolling = enabled;
+                if !enabled {
+                    self.kinetic = None;
+                }
+            }
+            Action::KineticScroll => {
+                if let Some((vx, vy)) = self.kinetic {
+                    self.pan(vx, vy);
+                    let (vx, vy) = (vx * KINETIC_FRICTION, vy * KINETIC_FRICTION);
+                    self.kinetic = if vx.hypot(vy) > KINETIC_MIN_VELOCITY {
+                        Some((vx, vy))
+                    } else {
+                        None
+                    };
                 }
             }
+            Action::Zoom(ZoomEvent { dscale }) => {
+                let anchor = self.pointer_old.unwrap_or_else(|| self.viewport_center());
+                self.zoom(self.zoom_level() * ZOOM_STEP.powf(-dscale), anchor);
+            }
+            Action::ZoomIn => self.zoom(self.zoom_level() * ZOOM_STEP, self.viewport_center()),
+            Action::ZoomOut => self.zoom(self.zoom_level() / ZOOM_STEP, self.viewport_center()),
+            Action::ZoomPreset(preset) => self.zoom_preset(preset),
             Action::Motion(MotionEvent { x, y }) => {
                 self.pointer_old = Some((x, y));
             }
+            Action::Open(path) => match storage::load(&path) {
+                Ok(document) => {
+                    self.pages = document.pages.into_owned();
+                    self.layout = document.layout;
+                    self.page = 0;
+                    self.viewport.transform = document.transform;
+                    self.tool = document.tool;
+                    self.stroke = None;
+                    self.text_edit = None;
+                    self.selection = None;
+                    self.selection_transform = None;
+                    self.history.clear();
+                    self.file = Some(path);
+                }
+                Err(err) => log::warn!("Failed to open {}: {}", path.display(), err),
+            },
+            Action::Save => {
+                if let Some(path) = self.file.clone() {
+                    self.save(path);
+                }
+            }
+            Action::SaveAs(path) => self.save(path),
+            Action::Import(path) => {
+                let has_extension =
+                    |extension: &str| path.extension().map_or(false, |e| e == extension);
+                let imported: Result<_, Box<dyn std::error::Error>> =
+                    if has_extension(xoj::FILE_EXTENSION) {
+                        xoj::read(&path)
+                            .map(|document| {
+                                let (pages, warnings) = xoj::import(document);
+                                for warning in warnings {
+                                    log::warn!("{}: {}", path.display(), warning);
+                                }
+                                pages
+                            })
+                            .map_err(Into::into)
+                    } else if has_extension(pdf::FILE_EXTENSION) {
+                        // every PDF page becomes the background of a page to annotate
+                        pdf::load(&path).map_err(Into::into)
+                    } else {
+                        xopp::read(&path).map(xopp::import).map_err(Into::into)
+                    };
+                match imported {
+                    Ok(pages) => {
+                        self.pages = pages;
+                        self.layout = Layout::Paged;
+                        self.page = 0;
+                        self.stroke = None;
+                        self.text_edit = None;
+                        self.selection = None;
+                        self.selection_transform = None;
+                        self.history.clear();
+                        self.file = None;
+                    }
+                    Err(err) => log::warn!("Failed to import {}: {}", path.display(), err),
+                }
+            }
+            Action::ExportXopp(path) => {
+                let pages = xopp::export(&self.pages);
+                if let Err(err) = xopp::write(&path, &pages) {
+                    log::warn!("Failed to export {}: {}", path.display(), err);
+                }
+            }
+            Action::ExportSvg(path, area) => {
+                if let Err(err) = export::export_svg(
+                    &path,
+                    &self.pages,
+                    self.layout,
+                    &self.viewport,
+                    self.selection_envelope(),
+                    area,
+                ) {
+                    log::warn!("Failed to export {}: {}", path.display(), err);
+                }
+            }
+            Action::ExportPdf(path, range) => {
+                let result = match self.layout {
+                    Layout::Paged => export::export_pdf(&path, &self.pages, range),
+                    Layout::Canvas => {
+                        export::export_pdf(&path, &canvas::to_pages(&self.pages[0]), range)
+                    }
+                };
+                if let Err(err) = result {
+                    log::warn!("Failed to export {}: {}", path.display(), err);
+                }
+            }
+            Action::ExportPng(path, area) => {
+                if let Err(err) = export::export_png(
+                    &path,
+                    &self.pages,
+                    self.layout,
+                    &self.viewport,
+                    self.selection_envelope(),
+                    area,
+                    self.png_options,
+                ) {
+                    log::warn!("Failed to export {}: {}", path.display(), err);
+                }
+            }
+            Action::PngDpi(dpi) => {
+                self.png_options.dpi = dpi;
+            }
+            Action::PngTransparent(transparent) => {
+                self.png_options.transparent = transparent;
+            }
+            Action::HistoryDepth(depth) => {
+                self.history.set_depth(depth);
+            }
+            Action::Undo => {
+                if self.stroke.is_none() && self.selection_transform.is_none() {
+                    self.selection = None;
+                    self.history.undo(&mut self.pages, &mut self.layout);
+                    self.page = self.page.min(self.pages.len() - 1);
+                }
+            }
+            Action::Redo => {
+                if self.stroke.is_none() && self.selection_transform.is_none() {
+                    self.selection = None;
+                    self.history.redo(&mut self.pages, &mut self.layout);
+                    self.page = self.page.min(self.pages.len() - 1);
+                }
+            }
+            Action::Copy => {
+                if let Some(selection) = &self.selection {
+                    self.copied = Some(selection.elements.clone());
+                }
+            }
+            Action::Cut => {
+                if self.selection_transform.is_none() {
+                    if let Some(selection) = self.selection.take() {
+                        self.copied = Some(selection.elements.clone());
+                        let (page, layer) = (selection.page, selection.layer);
+                        self.commit(
+                            selection
+                                .elements
+                                .into_iter()
+                                .map(|e| Change::Remove(page, layer, e))
+                                .collect(),
+                        );
+                    }
+                }
+            }
+            Action::Paste(elements) => self.paste(elements),
+            Action::PasteImage(image) => self.paste_image(image),
+            Action::InsertImage(path) => match Image::load(&path) {
+                Ok(image) => self.paste_image(image),
+                Err(err) => log::warn!("Failed to insert {}: {}", path.display(), err),
+            },
+            // a canvas stays a single page
+            Action::InsertPageBefore
+            | Action::InsertPageAfter
+            | Action::DuplicatePage
+            | Action::DeletePage
+            | Action::MovePageUp
+            | Action::MovePageDown
+                if self.layout == Layout::Canvas => {}
+            Action::InsertPageBefore => {
+                let page = self.blank_page();
+                self.edit_pages(Change::InsertPage(self.page, page), self.page);
+            }
+            Action::InsertPageAfter => {
+                let page = self.blank_page();
+                self.edit_pages(Change::InsertPage(self.page + 1, page), self.page + 1);
+            }
+            Action::DuplicatePage => {
+                let page = self.pages[self.page].clone();
+                self.edit_pages(Change::InsertPage(self.page + 1, page), self.page + 1);
+            }
+            Action::DeletePage => {
+                if self.pages.len() > 1 {
+                    let page = self.pages[self.page].clone();
+                    let current = self.page.min(self.pages.len() - 2);
+                    self.edit_pages(Change::RemovePage(self.page, page), current);
+                }
+            }
+            Action::MovePageUp => {
+                if self.page > 0 {
+                    self.edit_pages(Change::MovePage(self.page, self.page - 1), self.page - 1);
+                }
+            }
+            Action::MovePageDown => {
+                if self.page + 1 < self.pages.len() {
+                    self.edit_pages(Change::MovePage(self.page, self.page + 1), self.page + 1);
+                }
+            }
+            Action::PageSize(size) => {
+                let old = self.pages[self.page].size;
+                if old != size {
+                    self.edit_pages(Change::ResizePage(self.page, old, size), self.page);
+                }
+            }
+            Action::Layout(layout) => {
+                if layout == Layout::Canvas && self.pages.iter().any(|page| page.pdf.is_some()) {
+                    log::warn!("Pages with a PDF background can not be put on a canvas");
+                } else if layout != self.layout {
+                    let pages = match layout {
+                        Layout::Paged => {
+                            // start at the first page like a new document, keeping the zoom
+                            self.viewport.transform.m31 = -PAGE_GAP;
+                            self.viewport.transform.m32 = -PAGE_GAP;
+                            canvas::to_pages(&self.pages[0])
+                        }
+                        Layout::Canvas => vec![canvas::to_canvas(&self.pages)],
+                    };
+                    let change = Change::SetLayout(self.layout, self.pages.clone(), layout, pages);
+                    self.edit_pages(change, 0);
+                }
+            }
+            Action::BackgroundPattern(pattern) => self.set_background(Background {
+                pattern,
+                ..self.pages[self.page].background
+            }),
+            Action::BackgroundSpacing(spacing) => self.set_background(Background {
+                spacing,
+                ..self.pages[self.page].background
+            }),
+            Action::BackgroundColor(color) => self.set_background(Background {
+                color,
+                ..self.pages[self.page].background
+            }),
+            Action::AddLayer => {
+                let page = &self.pages[self.page];
+                let layer = Layer::new(Layer::default_name(page.layers.len()));
+                let index = page.layer + 1;
+                self.edit_layers(vec![Change::InsertLayer(self.page, index, layer)], index);
+            }
+            Action::DeleteLayer => {
+                let page = &self.pages[self.page];
+                if page.layers.len() > 1 {
+                    let layer = page.active_layer().clone();
+                    let index = page.layer;
+                    self.edit_layers(
+                        vec![Change::RemoveLayer(self.page, index, layer)],
+                        index.saturating_sub(1),
+                    );
+                }
+            }
+            Action::MoveLayerUp => {
+                let index = self.pages[self.page].layer;
+                if index + 1 < self.pages[self.page].layers.len() {
+                    self.edit_layers(
+                        vec![Change::MoveLayer(self.page, index, index + 1)],
+                        index + 1,
+                    );
+                }
+            }
+            Action::MoveLayerDown => {
+                let index = self.pages[self.page].layer;
+                if index > 0 {
+                    self.edit_layers(
+                        vec![Change::MoveLayer(self.page, index, index - 1)],
+                        index - 1,
+                    );
+                }
+            }
+            Action::MergeLayerDown => {
+                let page = &self.pages[self.page];
+                let index = page.layer;
+                if index > 0 && page.layers[index].editable() && page.layers[index - 1].editable() {
+                    let layer = page.active_layer().clone();
+                    let mut changes = layer
+                        .elements
+                        .iter()
+                        .map(|e| Change::Insert(self.page, index - 1, e.clone()))
+                        .collect::<Vec<_>>();
+                    changes.push(Change::RemoveLayer(self.page, index, layer));
+                    self.edit_layers(changes, index - 1);
+                }
+            }
+            Action::SelectLayer(index) => {
+                let page = &mut self.pages[self.page];
+                if index != page.layer && index < page.layers.len() {
+                    page.layer = index;
+                    self.finish_text();
+                    self.selection = None;
+                }
+            }
+            Action::LayerVisible(index, visible) => {
+                if let Some(layer) = self.pages[self.page].layers.get_mut(index) {
+                    layer.visible = visible;
+                    self.selection = None;
+                }
+            }
+            Action::LayerLocked(index, locked) => {
+                if let Some(layer) = self.pages[self.page].layers.get_mut(index) {
+                    layer.locked = locked;
+                    self.selection = None;
+                }
+            }
+            Action::RenameLayer(index, name) => {
+                if let Some(layer) = self.pages[self.page].layers.get_mut(index) {
+                    layer.name = name;
+                }
+            }
+        }
+    }
+
+    fn save(&mut self, path: PathBuf) {
+        let document =
+            DocumentFile::new(&self.pages, self.layout, self.viewport.transform, self.tool);
+        match storage::save(&path, &document) {
+            Ok(_) => self.file = Some(path),
+            Err(err) => log::warn!("Failed to save {}: {}", path.display(), err),
+        }
+    }
+
+    /// pan to the pointer offset `(x, y)`, relative to the previous offset in `scroll_state`
+    fn scroll(&mut self, x: f64, y: f64) {
+        let ddx;
+        let ddy;
+        if let Some(state) = &mut self.scroll_state {
+            ddx = x - state.x_old;
+            ddy = y - state.y_old;
+            state.x_old = x;
+            state.y_old = y;
+            // a release where the pointer rests is no movement
+            if ddx != 0.0 || ddy != 0.0 {
+                state.sample(x, y);
+            }
+        } else {
+            ddx = x;
+            ddy = y;
+        }
+        self.pan(ddx, ddy);
+    }
+
+    /// pixels per document unit
+    fn zoom_level(&self) -> f64 {
+        1.0 / self.viewport.transform.m11
+    }
+
+    fn viewport_center(&self) -> (f64, f64) {
+        (
+            self.viewport.width as f64 / 2.0,
+            self.viewport.height as f64 / 2.0,
+        )
+    }
+
+    /// set the zoom in pixels per document unit, within the limits, keeping the document point at
+    /// `anchor` in viewport coordinates where it is
+    fn zoom(&mut self, zoom: f64, anchor: (f64, f64)) {
+        let point = self.viewport.normalize_from_viewport(anchor);
+        let scale = 1.0 / zoom.max(MIN_ZOOM).min(MAX_ZOOM);
+        let transform = &mut self.viewport.transform;
+        transform.m11 = scale;
+        transform.m22 = scale;
+        transform.m31 = point.0 - anchor.0 * scale;
+        transform.m32 = point.1 - anchor.1 * scale;
+    }
+
+    fn zoom_preset(&mut self, preset: ZoomPreset) {
+        let center = self.viewport_center();
+        if preset == ZoomPreset::Actual {
+            self.zoom(1.0, center);
+            return;
+        }
+        let envelope = match self.layout {
+            Layout::Paged => self.pages.page_envelope(self.page),
+            Layout::Canvas => match self.pages[0].content_envelope() {
+                Some(envelope) => envelope,
+                None => return,
+            },
+        };
+        let (lower, upper) = (envelope.lower(), envelope.upper());
+        // leave a gap around the page
+        let width = upper.x() - lower.x() + 2.0 * PAGE_GAP;
+        let height = upper.y() - lower.y() + 2.0 * PAGE_GAP;
+        let fit_width = self.viewport.width as f64 / width;
+        // document point to show at the centre of the viewport
+        let (zoom, center_y) = match preset {
+            ZoomPreset::FitPage => (
+                fit_width.min(self.viewport.height as f64 / height),
+                (lower.y() + upper.y()) / 2.0,
+            ),
+            // fit width keeps the part of the page at the centre in view
+            _ => (fit_width, self.viewport.normalize_from_viewport(center).1),
+        };
+        self.zoom(zoom, center);
+        let scale = self.viewport.transform.m11;
+        self.viewport.transform.m31 = (lower.x() + upper.x()) / 2.0 - center.0 * scale;
+        self.viewport.transform.m32 = center_y - center.1 * scale;
+    }
+
+    /// move the viewport by `(dx, dy)` screen pixels
+    fn pan(&mut self, dx: f64, dy: f64) {
+        self.viewport.transform.m31 -= dx * self.viewport.transform.m11;
+        self.viewport.transform.m32 -= dy * self.viewport.transform.m11;
+    }
+
+    /// erase along a segment given in viewport coordinates
+    fn erase(&mut self, start: (f64, f64), end: (f64, f64)) {
+        let start = self.viewport.normalize_from_viewport(start);
+        let end = self.viewport.normalize_from_viewport(end);
+        let radius = self.eraser_radius * self.viewport.transform.m11;
+        let changes = self.pages.erase(start, end, radius);
+        self.erased.extend(changes);
+    }
+
+    /// style the palette changes, the highlighter's while it is the tool and the pen's otherwise
+    fn active_style(&mut self) -> &mut StrokeStyle {
+        if self.tool == Tool::Highlighter {
+            &mut self.highlighter
+        } else {
+            &mut self.pen
+        }
+    }
+
+    fn set_tool(&mut self, tool: Tool) {
+        self.finish_text();
+        self.finish_selection_transform();
+        self.selection = None;
+        self.tool = tool;
+    }
+
+    /// last pointer position in viewport coordinates, the centre if the pointer never moved
+    fn pointer(&self) -> (f64, f64) {
+        self.pointer_old.unwrap_or((
+            self.viewport.width as f64 / 2.0,
+            self.viewport.height as f64 / 2.0,
+        ))
+    }
+
+    /// page under the pointer at `pointer` in viewport coordinates
+    fn page_at(&self, pointer: (f64, f64)) -> usize {
+        self.pages
+            .page_at(self.viewport.normalize_from_viewport(pointer))
+    }
+
+    /// viewport mapping onto the coordinates of the current page
+    fn page_viewport(&self) -> Viewport {
+        self.pages.page_viewport(self.page, &self.viewport)
+    }
+
+    /// viewport mapping onto the coordinates of the page of the selection
+    fn selection_viewport(&self) -> Viewport {
+        let page = self.selection.as_ref().map_or(self.page, |s| s.page);
+        self.pages.page_viewport(page, &self.viewport)
+    }
+
+    /// envelope of the selection in document coordinates
+    fn selection_envelope(&self) -> Option<AABB<Point<f64>>> {
+        self.selection.as_ref().map(|selection| {
+            let envelope = selection.envelope();
+            let (x, y) = self.pages.page_origin(selection.page);
+            let (lower, upper) = (envelope.lower(), envelope.upper());
+            AABB::from_corners(
+                (lower.x() + x, lower.y() + y).into(),
+                (upper.x() + x, upper.y() + y).into(),
+            )
+        })
+    }
+
+    /// edit the text box at `position` in coordinates of the current page, or start a new one there
+    fn start_text(&mut self, position: (f64, f64)) {
+        let page = &mut self.pages[self.page];
+        let layer = page.layer;
+        if !page.active_layer().editable() {
+            return;
+        }
+        let elements = &mut page.active_layer_mut().elements;
+        let hit = elements
+            .locate_in_envelope_intersecting(&AABB::from_point(position.into()))
+            .find_map(|element| match element {
+                Element::Text(text) => Some(text.clone()),
+                _ => None,
+            });
+        self.text_edit = Some(match hit {
+            Some(text) => {
+                elements.remove(&Element::Text(text.clone()));
+                TextEdit {
+                    page: self.page,
+                    layer,
+                    position: text.position,
+                    text: text.text.clone(),
+                    style: text.style.clone(),
+                    original: Some(text),
+                }
+            }
+            None => TextEdit {
+                page: self.page,
+                layer,
+                position,
+                text: String::new(),
+                style: TextStyle {
+                    color: self.pen.color,
+                    ..self.text_style.clone()
+                },
+                original: None,
+            },
+        });
+    }
+
+    /// put the edited text box back into the document as one undoable step
+    fn finish_text(&mut self) {
+        let edit = match self.text_edit.take() {
+            Some(edit) => edit,
+            None => return,
+        };
+        let mut changes = Vec::new();
+        if let Some(original) = edit.original {
+            let elements = &mut self.pages[edit.page].layers[edit.layer].elements;
+            if original.text == edit.text {
+                elements.insert(Element::Text(original));
+                return;
+            }
+            // the original was taken out when editing started
+            elements.insert(Element::Text(original.clone()));
+            changes.push(Change::Remove(
+                edit.page,
+                edit.layer,
+                Element::Text(original),
+            ));
+        }
+        if !edit.text.trim().is_empty() {
+            let text = TextBox::new(edit.position, edit.text, edit.style);
+            changes.push(Change::Insert(edit.page, edit.layer, Element::Text(text)));
+        }
+        self.commit(changes);
+    }
+
+    /// insert `elements` centred on the pointer as one undoable step, selected if a selection tool
+    /// is active
+    fn paste(&mut self, elements: Vec<Element>) {
+        if elements.is_empty() || self.stroke.is_some() || self.selection_transform.is_some() {
+            return;
+        }
+        let pointer = self.pointer();
+        self.page = self.page_at(pointer);
+        let page = &self.pages[self.page];
+        if !page.active_layer().editable() {
+            return;
+        }
+        let target = self.page_viewport().normalize_from_viewport(pointer);
+        let pasted = Selection {
+            page: self.page,
+            layer: page.layer,
+            elements,
+        };
+        let center = pasted.envelope().center();
+        let pasted = pasted.transformed(&Transform2D::translation(
+            target.0 - center.x(),
+            target.1 - center.y(),
+        ));
+        self.commit(
+            pasted
+                .elements
+                .iter()
+                .map(|e| Change::Insert(pasted.page, pasted.layer, e.clone()))
+                .collect(),
+        );
+        self.selection = match self.tool {
+            Tool::Select(_) => Some(pasted),
+            _ => None,
+        };
+    }
+
+    /// paste `image` shrunk to fit onto the page under the pointer
+    fn paste_image(&mut self, mut image: Image) {
+        image.fit(self.pages[self.page_at(self.pointer())].size.dimensions());
+        self.paste(vec![Element::Image(image)]);
+    }
+
+    /// empty page with the size and background of the current page
+    fn blank_page(&self) -> Page {
+        let current = &self.pages[self.page];
+        Page {
+            background: current.background,
+            ..Page::new(current.size)
+        }
+    }
+
+    /// change the background of the current page as one undoable step
+    fn set_background(&mut self, background: Background) {
+        let old = self.pages[self.page].background;
+        if old != background {
+            self.commit(vec![Change::SetBackground(self.page, old, background)]);
+        }
+    }
+
+    /// add `element` to the active layer of the current page as one undoable step, unless the layer
+    /// is locked or hidden
+    fn insert(&mut self, element: Element) {
+        let page = &self.pages[self.page];
+        if page.active_layer().editable() {
+            let layer = page.layer;
+            self.commit(vec![Change::Insert(self.page, layer, element)]);
+        }
+    }
+
+    /// apply changes to the layers of the current page as one undoable step and make `layer` the
+    /// active layer
+    fn edit_layers(&mut self, changes: Vec<Change>, layer: usize) {
+        // layer indices of the selection would be off
+        self.selection = None;
+        self.commit(changes);
+        self.pages[self.page].layer = layer;
+    }
+
+    /// apply a change to the pages as one undoable step and make `page` the current page
+    fn edit_pages(&mut self, change: Change, page: usize) {
+        // page indices of the selection would be off
+        self.selection = None;
+        self.commit(vec![change]);
+        self.page = page;
+    }
+
+    /// put the dragged selection back into the document as one undoable step
+    fn finish_selection_transform(&mut self) {
+        let transform = match self.selection_transform.take() {
+            Some(transform) => transform.transform,
+            None => return,
+        };
+        let selection = match self.selection.take() {
+            Some(selection) => selection,
+            None => return,
+        };
+        // the originals were taken out when the drag started
+        let layer = &mut self.pages[selection.page].layers[selection.layer];
+        for element in &selection.elements {
+            layer.elements.insert(element.clone());
+        }
+        if transform == Transform2D::identity() {
+            self.selection = Some(selection);
+            return;
+        }
+        let transformed = selection.transformed(&transform);
+        let (page, layer) = (selection.page, selection.layer);
+        let mut changes = selection
+            .elements
+            .into_iter()
+            .map(|e| Change::Remove(page, layer, e))
+            .collect::<Vec<_>>();
+        changes.extend(
+            transformed
+                .elements
+                .iter()
+                .map(|e| Change::Insert(page, layer, e.clone())),
+        );
+        self.commit(changes);
+        self.selection = Some(transformed);
+    }
+
+    /// rebuild the previewed shape from the current drag and modifiers
+    fn update_shape(&mut self) {
+        if let (Some(drag), Some(stroke)) = (&self.shape_drag, &mut self.stroke) {
+            stroke.line = drag.outline(self.shape_modifiers);
+        }
+    }
+
+    /// apply changes to the document as a single undoable step
+    fn commit(&mut self, changes: Vec<Change>) {
+        for change in &changes {
+            change.apply(&mut self.pages, &mut self.layout);
         }
+        self.history.record(changes);
     }
 }
@@ -1,31 +1,100 @@
 use euclid::default::Transform2D;
-use gtk::gdk::ffi::{GDK_AXIS_X, GDK_AXIS_Y};
-use gtk::gdk::BUTTON_MIDDLE;
-use gtk::gdk::{Rectangle, BUTTON_SECONDARY};
+use gtk::gdk::ffi::{GDK_AXIS_PRESSURE, GDK_AXIS_X, GDK_AXIS_Y};
+use gtk::gdk::{keys, BUTTON_MIDDLE};
+use gtk::gdk::{AxisFlags, AxisUse, InputSource, ModifierType, Rectangle, BUTTON_SECONDARY};
 use gtk::gio::{Menu, SimpleAction};
-use gtk::glib::MainContext;
 use gtk::glib::PRIORITY_DEFAULT;
-use gtk::ApplicationWindow;
+use gtk::glib::{MainContext, SyncSender};
 use gtk::EventSequenceState;
-use gtk::{glib, EventControllerScroll, EventControllerScrollFlags, Inhibit};
+use gtk::{gdk::RGBA, ColorChooserDialog, FontChooserDialog};
+use gtk::{glib, pango, EventControllerScroll, EventControllerScrollFlags, Inhibit};
 use gtk::{prelude::*, GestureClick, PopoverMenu, PopoverMenuFlags, PositionType};
-use gtk::{Application, EventControllerMotion};
+use gtk::{Align, CssProvider, Overlay, TextView, STYLE_PROVIDER_PRIORITY_APPLICATION};
+use gtk::{Application, EventControllerKey, EventControllerMotion};
+use gtk::{ApplicationWindow, FileChooserAction, FileChooserNative, FileFilter, ResponseType};
+use gtk::{Dialog, DialogFlags, Grid, Label, SpinButton};
 use ring_channel::*;
-use rstar::RTree;
 use std::cell::RefCell;
 use std::num::NonZeroUsize;
+use std::path::PathBuf;
 use std::rc::Rc;
 
+mod background;
+mod canvas;
+mod clipboard;
 mod custom_widget;
+mod export;
+mod history;
+mod image;
+mod layer_panel;
 mod logic;
+mod pdf;
 mod quadtree;
+mod recognizer;
+mod selection;
+mod shapes;
+mod storage;
+mod text;
+mod xoj;
+mod xopp;
 
+use background::Pattern;
+use canvas::Layout;
 use custom_widget::MainWidget;
+use export::{ExportArea, PaperSize, PngOptions, UNITS_PER_INCH};
+use history::{History, DEFAULT_HISTORY_DEPTH};
+use layer_panel::LayerPanel;
 use logic::{
     Action, AppState, MotionEvent, MouseMotionAction, MousePressAction, MouseReleaseAction,
-    ScrollEvent, Tool, Widgets, ZoomEvent,
+    ScrollEvent, Tool, Widgets, ZoomEvent, ZoomPreset, DEFAULT_ERASER_RADIUS,
+    DEFAULT_HIGHLIGHTER_STYLE, KINETIC_INTERVAL,
 };
-use quadtree::Viewport;
+use quadtree::{Page, Rgba, StrokeStyle, Viewport, LINE_WIDTH, PAGE_GAP};
+use recognizer::{DEFAULT_TOLERANCE, LOOSE_TOLERANCE, STRICT_TOLERANCE};
+use selection::SelectionKind;
+use shapes::{Shape, ShapeModifiers};
+use text::TextStyle;
+
+/// pen colours offered in the menu as (name, `#rrggbbaa`), bound to <Primary>1 and up
+const PALETTE: &[(&str, &str)] = &[
+    ("Black", "#000000ff"),
+    ("Blue", "#0000ffff"),
+    ("Red", "#ff0000ff"),
+    ("Green", "#008000ff"),
+    ("Orange", "#ff8000ff"),
+    ("Yellow", "#ffff00ff"),
+    ("Magenta", "#ff00ffff"),
+    ("Gray", "#808080ff"),
+];
+
+/// paper colours offered in the menu as (name, `#rrggbbaa`)
+const PAPER_COLORS: &[(&str, &str)] = &[
+    ("White", "#ffffffff"),
+    ("Ivory", "#fffbe6ff"),
+    ("Yellow", "#ffff80ff"),
+    ("Blue", "#a0e8ffff"),
+    ("Green", "#80ffc0ff"),
+    ("Pink", "#ffc0d4ff"),
+];
+
+/// millimetres per inch, custom page sizes are entered in millimetres
+const MM_PER_INCH: f64 = 25.4;
+
+/// file chooser filters as (name, extensions)
+const NATIVE_FILTER: (&str, &[&str]) = ("XournalR documents", &[storage::FILE_EXTENSION]);
+const XOPP_FILTER: (&str, &[&str]) = ("Xournal++ documents", &[xopp::FILE_EXTENSION]);
+const SVG_FILTER: (&str, &[&str]) = ("SVG images", &["svg"]);
+const PDF_FILTER: (&str, &[&str]) = ("PDF documents", &[pdf::FILE_EXTENSION]);
+const PNG_FILTER: (&str, &[&str]) = ("PNG images", &["png"]);
+const IMAGE_FILTER: (&str, &[&str]) = ("Images", &["png", "jpg", "jpeg"]);
+const IMPORT_FILTER: (&str, &[&str]) = (
+    "Xournal and PDF documents",
+    &[
+        xopp::FILE_EXTENSION,
+        xoj::FILE_EXTENSION,
+        pdf::FILE_EXTENSION,
+    ],
+);
 
 static GLIB_LOGGER: glib::GlibLogger = glib::GlibLogger::new(
     glib::GlibLoggerFormat::Plain,
@@ -52,6 +121,40 @@ fn build_ui(app: &Application) {
     let (sender, receiver) = MainContext::sync_channel::<Action>(PRIORITY_DEFAULT, 10);
     widget.set_size_channel(sender.clone());
 
+    let state = Rc::new(RefCell::new(AppState {
+        pages: vec![Page::new(PaperSize::A4)],
+        layout: Layout::Paged,
+        page: 0,
+        stroke: None,
+        pen: StrokeStyle::default(),
+        highlighter: DEFAULT_HIGHLIGHTER_STYLE,
+        viewport: Viewport {
+            width: 0,
+            height: 0,
+            // leave a gap above and left of the first page
+            transform: Transform2D::translation(-PAGE_GAP, -PAGE_GAP),
+        },
+        scroll_state: None,
+        pointer_old: None,
+        tool: Tool::Pen,
+        shape_drag: None,
+        shape_modifiers: ShapeModifiers::default(),
+        shape_recognition: false,
+        recognizer_tolerance: DEFAULT_TOLERANCE,
+        text_style: TextStyle::default(),
+        text_edit: None,
+        selection: None,
+        selection_transform: None,
+        copied: None,
+        eraser_radius: DEFAULT_ERASER_RADIUS,
+        kinetic_scrolling: true,
+        kinetic: None,
+        file: None,
+        png_options: PngOptions::default(),
+        history: History::new(DEFAULT_HISTORY_DEPTH),
+        erased: Vec::new(),
+    }));
+
     let tool_action = SimpleAction::new_stateful(
         "tool",
         Some(&String::static_variant_type()),
@@ -63,9 +166,17 @@ fn build_ui(app: &Application) {
         let state = state.unwrap();
         let tool_action = match state.to_string().as_str() {
             "'pen'" => Some(Action::ToolPen),
+            "'highlighter'" => Some(Action::ToolHighlighter),
             "'eraser'" => Some(Action::ToolEraser),
             "'obj_eraser'" => Some(Action::ToolObjEraser),
             "'hand'" => Some(Action::ToolHand),
+            "'line'" => Some(Action::ToolShape(Shape::Line)),
+            "'rectangle'" => Some(Action::ToolShape(Shape::Rectangle)),
+            "'ellipse'" => Some(Action::ToolShape(Shape::Ellipse)),
+            "'arrow'" => Some(Action::ToolShape(Shape::Arrow)),
+            "'text'" => Some(Action::ToolText),
+            "'select_rectangle'" => Some(Action::ToolSelect(SelectionKind::Rectangle)),
+            "'select_lasso'" => Some(Action::ToolSelect(SelectionKind::Lasso)),
             _ => None,
         };
         if let Some(tool_action) = tool_action {
@@ -75,15 +186,799 @@ fn build_ui(app: &Application) {
     });
     app.add_action(&tool_action);
 
+    let eraser_radius_action = SimpleAction::new_stateful(
+        "eraser_radius",
+        Some(&String::static_variant_type()),
+        &"medium".to_variant(),
+    );
+    eraser_radius_action.set_enabled(true);
+    let eraser_radius_sender = sender.clone();
+    eraser_radius_action.connect_activate(move |action, state| {
+        let state = state.unwrap();
+        let radius = match state.to_string().as_str() {
+            "'small'" => Some(2.0),
+            "'medium'" => Some(DEFAULT_ERASER_RADIUS),
+            "'large'" => Some(15.0),
+            _ => None,
+        };
+        if let Some(radius) = radius {
+            eraser_radius_sender
+                .send(Action::EraserRadius(radius))
+                .unwrap();
+            action.set_state(state);
+        }
+    });
+    app.add_action(&eraser_radius_action);
+
+    let pen_color_action = SimpleAction::new_stateful(
+        "pen_color",
+        Some(&String::static_variant_type()),
+        &Rgba::default().to_hex().to_variant(),
+    );
+    let pen_color_sender = sender.clone();
+    pen_color_action.connect_activate(move |action, state| {
+        let state = state.unwrap();
+        if let Some(color) = state.get::<String>().and_then(|hex| Rgba::from_hex(&hex)) {
+            pen_color_sender.send(Action::PenColor(color)).unwrap();
+            action.set_state(state);
+        }
+    });
+    app.add_action(&pen_color_action);
+    for (index, (_, hex)) in PALETTE.iter().enumerate().take(9) {
+        app.set_accels_for_action(
+            &format!("app.pen_color::{}", hex),
+            &[format!("<Primary>{}", index + 1).as_str()],
+        );
+    }
+
+    let custom_color_action = SimpleAction::new("custom_color", None);
+    let custom_color_window = window.clone();
+    let custom_color_state = state.clone();
+    custom_color_action.connect_activate(move |_, _| {
+        let dialog = ColorChooserDialog::new(Some("Pen Colour"), Some(&custom_color_window));
+        dialog.set_modal(true);
+        dialog.set_use_alpha(true);
+        let color = custom_color_state.borrow().pen.color;
+        dialog.set_rgba(&RGBA::new(
+            color.red as f32,
+            color.green as f32,
+            color.blue as f32,
+            color.alpha as f32,
+        ));
+        let pen_color_action = pen_color_action.clone();
+        dialog.connect_response(move |dialog, response| {
+            if response == ResponseType::Ok {
+                let rgba = dialog.rgba();
+                let color = Rgba::new(
+                    rgba.red() as f64,
+                    rgba.green() as f64,
+                    rgba.blue() as f64,
+                    rgba.alpha() as f64,
+                );
+                pen_color_action.activate(Some(&color.to_hex().to_variant()));
+            }
+            dialog.destroy();
+        });
+        dialog.show();
+    });
+    app.add_action(&custom_color_action);
+
+    let pen_width_action = SimpleAction::new_stateful(
+        "pen_width",
+        Some(&String::static_variant_type()),
+        &"medium".to_variant(),
+    );
+    let pen_width_sender = sender.clone();
+    pen_width_action.connect_activate(move |action, state| {
+        let state = state.unwrap();
+        let width = match state.to_string().as_str() {
+            "'fine'" => Some(1.0),
+            "'medium'" => Some(LINE_WIDTH),
+            "'thick'" => Some(4.0),
+            "'very_thick'" => Some(8.0),
+            _ => None,
+        };
+        if let Some(width) = width {
+            pen_width_sender.send(Action::PenWidth(width)).unwrap();
+            action.set_state(state);
+        }
+    });
+    app.add_action(&pen_width_action);
+    app.set_accels_for_action("app.pen_width::fine", &["<Alt>1"]);
+    app.set_accels_for_action("app.pen_width::medium", &["<Alt>2"]);
+    app.set_accels_for_action("app.pen_width::thick", &["<Alt>3"]);
+    app.set_accels_for_action("app.pen_width::very_thick", &["<Alt>4"]);
+
+    let text_font_action = SimpleAction::new("text_font", None);
+    let text_font_window = window.clone();
+    let text_font_sender = sender.clone();
+    let text_font_state = state.clone();
+    text_font_action.connect_activate(move |_, _| {
+        let dialog = FontChooserDialog::new(Some("Text Font"), Some(&text_font_window));
+        dialog.set_modal(true);
+        let style = text_font_state.borrow().text_style.clone();
+        let mut description = pango::FontDescription::from_string(&style.font);
+        description.set_size((style.size * pango::SCALE as f64) as i32);
+        dialog.set_font_desc(&description);
+        let sender = text_font_sender.clone();
+        dialog.connect_response(move |dialog, response| {
+            let description = dialog.font_desc().filter(|_| response == ResponseType::Ok);
+            if let Some(mut description) = description {
+                // the chooser offers sizes in points, which are document units
+                let size = description.size() as f64 / pango::SCALE as f64;
+                description.unset_fields(pango::FontMask::SIZE);
+                sender
+                    .send(Action::TextFont(description.to_string(), size))
+                    .unwrap();
+            }
+            dialog.destroy();
+        });
+        dialog.show();
+    });
+    app.add_action(&text_font_action);
+
+    let recognition_action =
+        SimpleAction::new_stateful("shape_recognition", None, &false.to_variant());
+    let recognition_sender = sender.clone();
+    recognition_action.connect_activate(move |action, _| {
+        let enabled = !action
+            .state()
+            .and_then(|state| state.get::<bool>())
+            .unwrap_or(false);
+        recognition_sender
+            .send(Action::ShapeRecognition(enabled))
+            .unwrap();
+        action.set_state(&enabled.to_variant());
+    });
+    app.add_action(&recognition_action);
+    app.set_accels_for_action("app.shape_recognition", &["<Primary>r"]);
+
+    let tolerance_action = SimpleAction::new_stateful(
+        "recognizer_tolerance",
+        Some(&String::static_variant_type()),
+        &"normal".to_variant(),
+    );
+    let tolerance_sender = sender.clone();
+    tolerance_action.connect_activate(move |action, state| {
+        let state = state.unwrap();
+        let tolerance = match state.to_string().as_str() {
+            "'strict'" => Some(STRICT_TOLERANCE),
+            "'normal'" => Some(DEFAULT_TOLERANCE),
+            "'loose'" => Some(LOOSE_TOLERANCE),
+            _ => None,
+        };
+        if let Some(tolerance) = tolerance {
+            tolerance_sender
+                .send(Action::RecognizerTolerance(tolerance))
+                .unwrap();
+            action.set_state(state);
+        }
+    });
+    app.add_action(&tolerance_action);
+
+    let layout_action = SimpleAction::new_stateful(
+        "layout",
+        Some(&String::static_variant_type()),
+        &"paged".to_variant(),
+    );
+    let layout_sender = sender.clone();
+    layout_action.connect_activate(move |action, state| {
+        let state = state.unwrap();
+        let layout = match state.to_string().as_str() {
+            "'paged'" => Some(Layout::Paged),
+            "'canvas'" => Some(Layout::Canvas),
+            _ => None,
+        };
+        if let Some(layout) = layout {
+            layout_sender.send(Action::Layout(layout)).unwrap();
+            action.set_state(state);
+        }
+    });
+    app.add_action(&layout_action);
+
+    let kinetic_action = SimpleAction::new_stateful("kinetic_scrolling", None, &true.to_variant());
+    let kinetic_action_sender = sender.clone();
+    kinetic_action.connect_activate(move |action, _| {
+        let enabled = !action
+            .state()
+            .and_then(|state| state.get::<bool>())
+            .unwrap_or(false);
+        kinetic_action_sender
+            .send(Action::KineticScrolling(enabled))
+            .unwrap();
+        action.set_state(&enabled.to_variant());
+    });
+    app.add_action(&kinetic_action);
+
+    let zoom_in_action = SimpleAction::new("zoom_in", None);
+    let zoom_in_sender = sender.clone();
+    zoom_in_action.connect_activate(move |_, _| {
+        zoom_in_sender.send(Action::ZoomIn).unwrap();
+    });
+    app.add_action(&zoom_in_action);
+    app.set_accels_for_action(
+        "app.zoom_in",
+        &["<Primary>plus", "<Primary>equal", "<Primary>KP_Add"],
+    );
+
+    let zoom_out_action = SimpleAction::new("zoom_out", None);
+    let zoom_out_sender = sender.clone();
+    zoom_out_action.connect_activate(move |_, _| {
+        zoom_out_sender.send(Action::ZoomOut).unwrap();
+    });
+    app.add_action(&zoom_out_action);
+    app.set_accels_for_action("app.zoom_out", &["<Primary>minus", "<Primary>KP_Subtract"]);
+
+    let zoom_action = SimpleAction::new("zoom", Some(&String::static_variant_type()));
+    let zoom_sender = sender.clone();
+    zoom_action.connect_activate(move |_, preset| {
+        let preset = match preset.unwrap().to_string().as_str() {
+            "'fit_page'" => ZoomPreset::FitPage,
+            "'fit_width'" => ZoomPreset::FitWidth,
+            "'actual'" => ZoomPreset::Actual,
+            _ => return,
+        };
+        zoom_sender.send(Action::ZoomPreset(preset)).unwrap();
+    });
+    app.add_action(&zoom_action);
+    app.set_accels_for_action("app.zoom::actual", &["<Primary>0"]);
+
+    let undo_action = SimpleAction::new("undo", None);
+    let undo_sender = sender.clone();
+    undo_action.connect_activate(move |_, _| {
+        undo_sender.send(Action::Undo).unwrap();
+    });
+    app.add_action(&undo_action);
+    app.set_accels_for_action("app.undo", &["<Primary>z"]);
+
+    let redo_action = SimpleAction::new("redo", None);
+    let redo_sender = sender.clone();
+    redo_action.connect_activate(move |_, _| {
+        redo_sender.send(Action::Redo).unwrap();
+    });
+    app.add_action(&redo_action);
+    app.set_accels_for_action("app.redo", &["<Primary><Shift>z"]);
+
+    let copy_action = SimpleAction::new("copy", None);
+    let copy_sender = sender.clone();
+    copy_action.connect_activate(move |_, _| {
+        copy_sender.send(Action::Copy).unwrap();
+    });
+    app.add_action(&copy_action);
+    app.set_accels_for_action("app.copy", &["<Primary>c"]);
+
+    let cut_action = SimpleAction::new("cut", None);
+    let cut_sender = sender.clone();
+    cut_action.connect_activate(move |_, _| {
+        cut_sender.send(Action::Cut).unwrap();
+    });
+    app.add_action(&cut_action);
+    app.set_accels_for_action("app.cut", &["<Primary>x"]);
+
+    let paste_action = SimpleAction::new("paste", None);
+    let paste_widget = widget.clone();
+    let paste_sender = sender.clone();
+    paste_action.connect_activate(move |_, _| {
+        clipboard::paste(&paste_widget.clipboard(), paste_sender.clone());
+    });
+    app.add_action(&paste_action);
+    app.set_accels_for_action("app.paste", &["<Primary>v"]);
+
+    let insert_image_action = SimpleAction::new("insert_image", None);
+    let insert_image_window = window.clone();
+    let insert_image_sender = sender.clone();
+    insert_image_action.connect_activate(move |_, _| {
+        choose_file(
+            &insert_image_window,
+            "Insert Image",
+            FileChooserAction::Open,
+            IMAGE_FILTER,
+            insert_image_sender.clone(),
+            Action::InsertImage,
+        );
+    });
+    app.add_action(&insert_image_action);
+
+    let page_actions = [
+        ("insert_page_before", Action::InsertPageBefore),
+        ("insert_page_after", Action::InsertPageAfter),
+        ("duplicate_page", Action::DuplicatePage),
+        ("delete_page", Action::DeletePage),
+        ("move_page_up", Action::MovePageUp),
+        ("move_page_down", Action::MovePageDown),
+        ("add_layer", Action::AddLayer),
+        ("delete_layer", Action::DeleteLayer),
+        ("move_layer_up", Action::MoveLayerUp),
+        ("move_layer_down", Action::MoveLayerDown),
+        ("merge_layer_down", Action::MergeLayerDown),
+    ];
+    for (name, page_action) in page_actions.iter().cloned() {
+        let action = SimpleAction::new(name, None);
+        let page_sender = sender.clone();
+        action.connect_activate(move |_, _| {
+            page_sender.send(page_action.clone()).unwrap();
+        });
+        app.add_action(&action);
+    }
+
+    let page_size_action = SimpleAction::new("page_size", Some(&String::static_variant_type()));
+    let page_size_window = window.clone();
+    let page_size_sender = sender.clone();
+    let page_size_state = state.clone();
+    page_size_action.connect_activate(move |_, size| {
+        let size = match size.unwrap().to_string().as_str() {
+            "'a4'" => PaperSize::A4,
+            "'letter'" => PaperSize::Letter,
+            "'custom'" => {
+                let state = page_size_state.borrow();
+                let current = state.pages[state.page].size;
+                choose_page_size(&page_size_window, current, page_size_sender.clone());
+                return;
+            }
+            _ => return,
+        };
+        page_size_sender.send(Action::PageSize(size)).unwrap();
+    });
+    app.add_action(&page_size_action);
+
+    let background_pattern_action =
+        SimpleAction::new("background_pattern", Some(&String::static_variant_type()));
+    let background_pattern_sender = sender.clone();
+    background_pattern_action.connect_activate(move |_, pattern| {
+        let pattern = match pattern.unwrap().to_string().as_str() {
+            "'plain'" => Pattern::Plain,
+            "'ruled'" => Pattern::Ruled,
+            "'graph'" => Pattern::Graph,
+            "'isometric'" => Pattern::Isometric,
+            "'dotted'" => Pattern::Dotted,
+            "'staves'" => Pattern::Staves,
+            _ => return,
+        };
+        background_pattern_sender
+            .send(Action::BackgroundPattern(pattern))
+            .unwrap();
+    });
+    app.add_action(&background_pattern_action);
+
+    let background_spacing_action =
+        SimpleAction::new("background_spacing", Some(&String::static_variant_type()));
+    let background_spacing_sender = sender.clone();
+    background_spacing_action.connect_activate(move |_, spacing| {
+        let millimetres = match spacing.unwrap().to_string().as_str() {
+            "'small'" => 5.0,
+            "'narrow'" => 6.35,
+            "'college'" => 7.1,
+            "'wide'" => 8.7,
+            _ => return,
+        };
+        let spacing = millimetres / MM_PER_INCH * UNITS_PER_INCH;
+        background_spacing_sender
+            .send(Action::BackgroundSpacing(spacing))
+            .unwrap();
+    });
+    app.add_action(&background_spacing_action);
+
+    let background_color_action =
+        SimpleAction::new("background_color", Some(&String::static_variant_type()));
+    let background_color_sender = sender.clone();
+    background_color_action.connect_activate(move |_, color| {
+        if let Some(color) = color
+            .unwrap()
+            .get::<String>()
+            .and_then(|hex| Rgba::from_hex(&hex))
+        {
+            background_color_sender
+                .send(Action::BackgroundColor(color))
+                .unwrap();
+        }
+    });
+    app.add_action(&background_color_action);
+
+    let open_action = SimpleAction::new("open", None);
+    let open_window = window.clone();
+    let open_sender = sender.clone();
+    open_action.connect_activate(move |_, _| {
+        choose_file(
+            &open_window,
+            "Open Document",
+            FileChooserAction::Open,
+            NATIVE_FILTER,
+            open_sender.clone(),
+            Action::Open,
+        );
+    });
+    app.add_action(&open_action);
+    app.set_accels_for_action("app.open", &["<Primary>o"]);
+
+    let save_action = SimpleAction::new("save", None);
+    let save_window = window.clone();
+    let save_sender = sender.clone();
+    let save_state = state.clone();
+    save_action.connect_activate(move |_, _| {
+        if save_state.borrow().file.is_some() {
+            save_sender.send(Action::Save).unwrap();
+        } else {
+            choose_file(
+                &save_window,
+                "Save Document",
+                FileChooserAction::Save,
+                NATIVE_FILTER,
+                save_sender.clone(),
+                Action::SaveAs,
+            );
+        }
+    });
+    app.add_action(&save_action);
+    app.set_accels_for_action("app.save", &["<Primary>s"]);
+
+    let save_as_action = SimpleAction::new("save_as", None);
+    let save_as_window = window.clone();
+    let save_as_sender = sender.clone();
+    save_as_action.connect_activate(move |_, _| {
+        choose_file(
+            &save_as_window,
+            "Save Document As",
+            FileChooserAction::Save,
+            NATIVE_FILTER,
+            save_as_sender.clone(),
+            Action::SaveAs,
+        );
+    });
+    app.add_action(&save_as_action);
+    app.set_accels_for_action("app.save_as", &["<Primary><Shift>s"]);
+
+    let import_action = SimpleAction::new("import", None);
+    let import_window = window.clone();
+    let import_sender = sender.clone();
+    import_action.connect_activate(move |_, _| {
+        choose_file(
+            &import_window,
+            "Import Document",
+            FileChooserAction::Open,
+            IMPORT_FILTER,
+            import_sender.clone(),
+            Action::Import,
+        );
+    });
+    app.add_action(&import_action);
+
+    let export_xopp_action = SimpleAction::new("export_xopp", None);
+    let export_xopp_window = window.clone();
+    let export_xopp_sender = sender.clone();
+    export_xopp_action.connect_activate(move |_, _| {
+        choose_file(
+            &export_xopp_window,
+            "Export as Xournal++",
+            FileChooserAction::Save,
+            XOPP_FILTER,
+            export_xopp_sender.clone(),
+            Action::ExportXopp,
+        );
+    });
+    app.add_action(&export_xopp_action);
+
+    let export_svg_action = SimpleAction::new("export_svg", Some(&String::static_variant_type()));
+    let export_svg_window = window.clone();
+    let export_svg_sender = sender.clone();
+    export_svg_action.connect_activate(move |_, area| {
+        let on_chosen: fn(PathBuf) -> Action = match area.unwrap().to_string().as_str() {
+            "'document'" => |path| Action::ExportSvg(path, ExportArea::Document),
+            "'viewport'" => |path| Action::ExportSvg(path, ExportArea::Viewport),
+            "'selection'" => |path| Action::ExportSvg(path, ExportArea::Selection),
+            _ => return,
+        };
+        choose_file(
+            &export_svg_window,
+            "Export as SVG",
+            FileChooserAction::Save,
+            SVG_FILTER,
+            export_svg_sender.clone(),
+            on_chosen,
+        );
+    });
+    app.add_action(&export_svg_action);
+
+    let export_pdf_action = SimpleAction::new("export_pdf", Some(&String::static_variant_type()));
+    let export_pdf_window = window.clone();
+    let export_pdf_sender = sender.clone();
+    let export_pdf_state = state.clone();
+    export_pdf_action.connect_activate(move |_, pages| match pages.unwrap().to_string().as_str() {
+        "'all'" => choose_file(
+            &export_pdf_window,
+            "Export as PDF",
+            FileChooserAction::Save,
+            PDF_FILTER,
+            export_pdf_sender.clone(),
+            |path| Action::ExportPdf(path, None),
+        ),
+        "'range'" => {
+            let state = export_pdf_state.borrow();
+            // a canvas is exported as the pages it is cut into
+            let page_count = match state.layout {
+                Layout::Paged => state.pages.len(),
+                Layout::Canvas => canvas::to_pages(&state.pages[0]).len(),
+            };
+            choose_page_range(&export_pdf_window, page_count, export_pdf_sender.clone());
+        }
+        _ => {}
+    });
+    app.add_action(&export_pdf_action);
+
+    let export_png_action = SimpleAction::new("export_png", Some(&String::static_variant_type()));
+    let export_png_window = window.clone();
+    let export_png_sender = sender.clone();
+    export_png_action.connect_activate(move |_, area| {
+        let on_chosen: fn(PathBuf) -> Action = match area.unwrap().to_string().as_str() {
+            "'document'" => |path| Action::ExportPng(path, ExportArea::Document),
+            "'viewport'" => |path| Action::ExportPng(path, ExportArea::Viewport),
+            "'selection'" => |path| Action::ExportPng(path, ExportArea::Selection),
+            _ => return,
+        };
+        choose_file(
+            &export_png_window,
+            "Export as PNG",
+            FileChooserAction::Save,
+            PNG_FILTER,
+            export_png_sender.clone(),
+            on_chosen,
+        );
+    });
+    app.add_action(&export_png_action);
+
+    let png_dpi_action = SimpleAction::new_stateful(
+        "png_dpi",
+        Some(&String::static_variant_type()),
+        &"150".to_variant(),
+    );
+    let png_dpi_sender = sender.clone();
+    png_dpi_action.connect_activate(move |action, state| {
+        let state = state.unwrap();
+        let dpi = match state.to_string().as_str() {
+            "'72'" => Some(72.0),
+            "'150'" => Some(150.0),
+            "'300'" => Some(300.0),
+            "'600'" => Some(600.0),
+            _ => None,
+        };
+        if let Some(dpi) = dpi {
+            png_dpi_sender.send(Action::PngDpi(dpi)).unwrap();
+            action.set_state(state);
+        }
+    });
+    app.add_action(&png_dpi_action);
+
+    let history_depth_action = SimpleAction::new_stateful(
+        "history_depth",
+        Some(&String::static_variant_type()),
+        &DEFAULT_HISTORY_DEPTH.to_string().to_variant(),
+    );
+    let history_depth_sender = sender.clone();
+    history_depth_action.connect_activate(move |action, state| {
+        let state = state.unwrap();
+        let depth = match state.to_string().as_str() {
+            "'10'" => Some(10),
+            "'50'" => Some(50),
+            "'100'" => Some(100),
+            "'500'" => Some(500),
+            _ => None,
+        };
+        if let Some(depth) = depth {
+            history_depth_sender
+                .send(Action::HistoryDepth(depth))
+                .unwrap();
+            action.set_state(state);
+        }
+    });
+    app.add_action(&history_depth_action);
+
+    let png_transparent_action =
+        SimpleAction::new_stateful("png_transparent", None, &false.to_variant());
+    let png_transparent_sender = sender.clone();
+    png_transparent_action.connect_activate(move |action, _| {
+        let transparent = !action
+            .state()
+            .and_then(|state| state.get::<bool>())
+            .unwrap_or(false);
+        png_transparent_sender
+            .send(Action::PngTransparent(transparent))
+            .unwrap();
+        action.set_state(&transparent.to_variant());
+    });
+    app.add_action(&png_transparent_action);
+
     let menu = Menu::new();
     menu.append(Some("Pen"), Some("app.tool::pen"));
+    menu.append(Some("Highlighter"), Some("app.tool::highlighter"));
     menu.append(Some("Eraser"), Some("app.tool::eraser"));
     menu.append(Some("Object Eraser"), Some("app.tool::obj_eraser"));
     menu.append(Some("Hand"), Some("app.tool::hand"));
+    let shape_menu = Menu::new();
+    shape_menu.append(Some("Line"), Some("app.tool::line"));
+    shape_menu.append(Some("Rectangle"), Some("app.tool::rectangle"));
+    shape_menu.append(Some("Ellipse"), Some("app.tool::ellipse"));
+    shape_menu.append(Some("Arrow"), Some("app.tool::arrow"));
+    let recognition_menu = Menu::new();
+    recognition_menu.append(Some("Recognize Shapes"), Some("app.shape_recognition"));
+    let tolerance_menu = Menu::new();
+    tolerance_menu.append(Some("Strict"), Some("app.recognizer_tolerance::strict"));
+    tolerance_menu.append(Some("Normal"), Some("app.recognizer_tolerance::normal"));
+    tolerance_menu.append(Some("Loose"), Some("app.recognizer_tolerance::loose"));
+    recognition_menu.append_section(None, &tolerance_menu);
+    shape_menu.append_section(None, &recognition_menu);
+    menu.append_submenu(Some("Shapes"), &shape_menu);
+    menu.append(Some("Text"), Some("app.tool::text"));
+    let select_menu = Menu::new();
+    select_menu.append(Some("Rectangle"), Some("app.tool::select_rectangle"));
+    select_menu.append(Some("Lasso"), Some("app.tool::select_lasso"));
+    menu.append_submenu(Some("Select"), &select_menu);
+    menu.append(Some("Text Font…"), Some("app.text_font"));
+    let eraser_menu = Menu::new();
+    eraser_menu.append(Some("Small"), Some("app.eraser_radius::small"));
+    eraser_menu.append(Some("Medium"), Some("app.eraser_radius::medium"));
+    eraser_menu.append(Some("Large"), Some("app.eraser_radius::large"));
+    menu.append_submenu(Some("Eraser Size"), &eraser_menu);
+    let color_menu = Menu::new();
+    let palette_menu = Menu::new();
+    for (name, hex) in PALETTE {
+        palette_menu.append(Some(name), Some(&format!("app.pen_color::{}", hex)));
+    }
+    color_menu.append_section(None, &palette_menu);
+    color_menu.append(Some("Custom…"), Some("app.custom_color"));
+    menu.append_submenu(Some("Pen Colour"), &color_menu);
+    let width_menu = Menu::new();
+    width_menu.append(Some("Fine"), Some("app.pen_width::fine"));
+    width_menu.append(Some("Medium"), Some("app.pen_width::medium"));
+    width_menu.append(Some("Thick"), Some("app.pen_width::thick"));
+    width_menu.append(Some("Very Thick"), Some("app.pen_width::very_thick"));
+    menu.append_submenu(Some("Pen Width"), &width_menu);
+    menu.append(Some("Kinetic Scrolling"), Some("app.kinetic_scrolling"));
+    let zoom_menu = Menu::new();
+    zoom_menu.append(Some("Zoom In"), Some("app.zoom_in"));
+    zoom_menu.append(Some("Zoom Out"), Some("app.zoom_out"));
+    zoom_menu.append(Some("100%"), Some("app.zoom::actual"));
+    zoom_menu.append(Some("Fit Page"), Some("app.zoom::fit_page"));
+    zoom_menu.append(Some("Fit Width"), Some("app.zoom::fit_width"));
+    menu.append_submenu(Some("Zoom"), &zoom_menu);
+    let edit_menu = Menu::new();
+    edit_menu.append(Some("Undo"), Some("app.undo"));
+    edit_menu.append(Some("Redo"), Some("app.redo"));
+    let history_depth_menu = Menu::new();
+    history_depth_menu.append(Some("10 Steps"), Some("app.history_depth::10"));
+    history_depth_menu.append(Some("50 Steps"), Some("app.history_depth::50"));
+    history_depth_menu.append(Some("100 Steps"), Some("app.history_depth::100"));
+    history_depth_menu.append(Some("500 Steps"), Some("app.history_depth::500"));
+    edit_menu.append_submenu(Some("Undo Steps"), &history_depth_menu);
+    edit_menu.append(Some("Cut"), Some("app.cut"));
+    edit_menu.append(Some("Copy"), Some("app.copy"));
+    edit_menu.append(Some("Paste"), Some("app.paste"));
+    edit_menu.append(Some("Insert Image…"), Some("app.insert_image"));
+    menu.append_section(None, &edit_menu);
+    let page_menu = Menu::new();
+    page_menu.append(Some("Insert Before"), Some("app.insert_page_before"));
+    page_menu.append(Some("Insert After"), Some("app.insert_page_after"));
+    page_menu.append(Some("Duplicate"), Some("app.duplicate_page"));
+    page_menu.append(Some("Delete"), Some("app.delete_page"));
+    page_menu.append(Some("Move Up"), Some("app.move_page_up"));
+    page_menu.append(Some("Move Down"), Some("app.move_page_down"));
+    let page_size_menu = Menu::new();
+    page_size_menu.append(Some("A4"), Some("app.page_size::a4"));
+    page_size_menu.append(Some("Letter"), Some("app.page_size::letter"));
+    page_size_menu.append(Some("Custom…"), Some("app.page_size::custom"));
+    page_menu.append_submenu(Some("Size"), &page_size_menu);
+    let background_menu = Menu::new();
+    let pattern_menu = Menu::new();
+    pattern_menu.append(Some("Plain"), Some("app.background_pattern::plain"));
+    pattern_menu.append(Some("Ruled"), Some("app.background_pattern::ruled"));
+    pattern_menu.append(Some("Graph"), Some("app.background_pattern::graph"));
+    pattern_menu.append(Some("Isometric"), Some("app.background_pattern::isometric"));
+    pattern_menu.append(Some("Dotted"), Some("app.background_pattern::dotted"));
+    pattern_menu.append(Some("Music Staves"), Some("app.background_pattern::staves"));
+    background_menu.append_section(None, &pattern_menu);
+    let spacing_menu = Menu::new();
+    spacing_menu.append(Some("5 mm"), Some("app.background_spacing::small"));
+    spacing_menu.append(
+        Some("Narrow (6.35 mm)"),
+        Some("app.background_spacing::narrow"),
+    );
+    spacing_menu.append(
+        Some("College (7.1 mm)"),
+        Some("app.background_spacing::college"),
+    );
+    spacing_menu.append(Some("Wide (8.7 mm)"), Some("app.background_spacing::wide"));
+    background_menu.append_submenu(Some("Spacing"), &spacing_menu);
+    let paper_color_menu = Menu::new();
+    for (name, hex) in PAPER_COLORS {
+        paper_color_menu.append(Some(name), Some(&format!("app.background_color::{}", hex)));
+    }
+    background_menu.append_submenu(Some("Colour"), &paper_color_menu);
+    page_menu.append_submenu(Some("Background"), &background_menu);
+    page_menu.append(Some("Layers"), Some("app.show_layers"));
+    let layout_menu = Menu::new();
+    layout_menu.append(Some("Pages"), Some("app.layout::paged"));
+    layout_menu.append(Some("Infinite Canvas"), Some("app.layout::canvas"));
+    page_menu.append_section(Some("Layout"), &layout_menu);
+    menu.append_submenu(Some("Page"), &page_menu);
+    let file_menu = Menu::new();
+    file_menu.append(Some("Open…"), Some("app.open"));
+    file_menu.append(Some("Save"), Some("app.save"));
+    file_menu.append(Some("Save As…"), Some("app.save_as"));
+    file_menu.append(Some("Import…"), Some("app.import"));
+    file_menu.append(Some("Export as Xournal++…"), Some("app.export_xopp"));
+    file_menu.append(Some("Export as SVG…"), Some("app.export_svg::document"));
+    file_menu.append(
+        Some("Export Viewport as SVG…"),
+        Some("app.export_svg::viewport"),
+    );
+    file_menu.append(
+        Some("Export Selection as SVG…"),
+        Some("app.export_svg::selection"),
+    );
+    file_menu.append(Some("Export as PDF…"), Some("app.export_pdf::all"));
+    file_menu.append(Some("Export Pages as PDF…"), Some("app.export_pdf::range"));
+    let png_menu = Menu::new();
+    png_menu.append(Some("Document…"), Some("app.export_png::document"));
+    png_menu.append(Some("Viewport…"), Some("app.export_png::viewport"));
+    png_menu.append(Some("Selection…"), Some("app.export_png::selection"));
+    let png_dpi_menu = Menu::new();
+    png_dpi_menu.append(Some("72 DPI"), Some("app.png_dpi::72"));
+    png_dpi_menu.append(Some("150 DPI"), Some("app.png_dpi::150"));
+    png_dpi_menu.append(Some("300 DPI"), Some("app.png_dpi::300"));
+    png_dpi_menu.append(Some("600 DPI"), Some("app.png_dpi::600"));
+    png_menu.append_section(None, &png_dpi_menu);
+    png_menu.append(Some("Transparent Background"), Some("app.png_transparent"));
+    file_menu.append_submenu(Some("Export as PNG"), &png_menu);
+    menu.append_section(None, &file_menu);
     let popover_menu = PopoverMenu::from_model_full(&menu, PopoverMenuFlags::empty());
     popover_menu.set_position(PositionType::Left);
     widget.set_popover_menu(&popover_menu);
 
+    // editor for text boxes, positioned over the canvas by `Widgets::update`
+    let text_view = TextView::new();
+    text_view.set_halign(Align::Start);
+    text_view.set_valign(Align::Start);
+    text_view.hide();
+    let text_css = CssProvider::new();
+    text_view
+        .style_context()
+        .add_provider(&text_css, STYLE_PROVIDER_PRIORITY_APPLICATION);
+    let sender_text = sender.clone();
+    text_view.buffer().connect_changed(move |buffer| {
+        let text = buffer.text(&buffer.start_iter(), &buffer.end_iter(), false);
+        sender_text
+            .send(Action::TextInput(text.to_string()))
+            .unwrap();
+    });
+    let text_key_controller = EventControllerKey::new();
+    let sender_text_commit = sender.clone();
+    text_key_controller.connect_key_pressed(move |_, key, _, _| {
+        if key == keys::constants::Escape {
+            sender_text_commit.send(Action::TextCommit).unwrap();
+            Inhibit(true)
+        } else {
+            Inhibit(false)
+        }
+    });
+    text_view.add_controller(&text_key_controller);
+    let overlay = Overlay::new();
+    overlay.set_child(Some(&widget));
+    overlay.add_overlay(&text_view);
+
+    // layers of the current page, kept up to date by `Widgets::update`
+    let layer_panel = LayerPanel::new(sender.clone());
+    overlay.add_overlay(&layer_panel.widget);
+    let show_layers_action = SimpleAction::new_stateful("show_layers", None, &false.to_variant());
+    let show_layers_panel = layer_panel.widget.clone();
+    show_layers_action.connect_activate(move |action, _| {
+        let shown = !action
+            .state()
+            .and_then(|state| state.get::<bool>())
+            .unwrap_or(false);
+        show_layers_panel.set_visible(shown);
+        action.set_state(&shown.to_variant());
+    });
+    app.add_action(&show_layers_action);
+    app.set_accels_for_action("app.show_layers", &["<Primary>l"]);
+
     let (frame_sender, frame_receiver) = ring_channel(NonZeroUsize::new(1).unwrap());
     widget.set_render_channel(frame_receiver);
 
@@ -92,60 +987,95 @@ fn build_ui(app: &Application) {
     gesture.connect_down(move |gesture, x, y| {
         gesture.set_state(EventSequenceState::Claimed);
         sender_gesture_down
-            .send(Action::MousePress(MousePressAction { x, y }))
+            .send(Action::MousePress(MousePressAction {
+                x,
+                y,
+                pressure: gesture.axis(AxisUse::Pressure),
+            }))
             .unwrap();
     });
     let sender_gesture_motion = sender.clone();
-    gesture.connect_motion(move |gesture, _x, _y| {
-        /*sender_gesture_motion
-        .send(Action::MouseMotion(MouseMotionAction { x, y }))
-        .unwrap();*/
-        let backlog = gesture.backlog();
-        if let Some(log) = backlog {
-            for l in log {
-                sender_gesture_motion
-                    .send(Action::MouseMotion(MouseMotionAction {
-                        x: l.axes()[GDK_AXIS_X as usize],
-                        y: l.axes()[GDK_AXIS_Y as usize],
-                    }))
-                    .unwrap();
-            }
+    gesture.connect_motion(move |gesture, x, y| {
+        match gesture.backlog().filter(|log| !log.is_empty()) {
+            // one action for the whole backlog, sending more than the channel holds would block
+            Some(log) => sender_gesture_motion
+                .send(Action::MouseMotions(
+                    log.iter()
+                        .map(|l| MouseMotionAction {
+                            x: l.axes()[GDK_AXIS_X as usize],
+                            y: l.axes()[GDK_AXIS_Y as usize],
+                            // devices without a pressure axis leave it at zero
+                            pressure: l
+                                .flags()
+                                .contains(AxisFlags::PRESSURE)
+                                .then(|| l.axes()[GDK_AXIS_PRESSURE as usize]),
+                        })
+                        .collect(),
+                ))
+                .unwrap(),
+            None => sender_gesture_motion
+                .send(Action::MouseMotion(MouseMotionAction {
+                    x,
+                    y,
+                    pressure: gesture.axis(AxisUse::Pressure),
+                }))
+                .unwrap(),
         }
     });
     let sender_gesture_up = sender.clone();
-    gesture.connect_motion(move |_gesture, x, y| {
+    gesture.connect_up(move |_, x, y| {
         sender_gesture_up
-            .send(Action::MouseRelease(MouseReleaseAction { x, y }))
+            .send(Action::MouseRelease(MouseReleaseAction {
+                x,
+                y,
+                // the pressure is near zero when the stylus lifts, keep the last one instead
+                pressure: None,
+            }))
             .unwrap();
     });
-    //widget.add_controller(&gesture);
+    widget.add_controller(&gesture);
 
+    // stylus input is handled by the stylus gesture above
     let gesture = gtk::GestureDrag::new();
     let sender_gesture_down = sender.clone();
-    gesture.connect_drag_begin(move |_gesture, x, y| {
+    gesture.connect_drag_begin(move |gesture, x, y| {
+        if is_stylus(gesture) {
+            return;
+        }
         sender_gesture_down
-            .send(Action::MousePress(MousePressAction { x, y }))
+            .send(Action::MousePress(MousePressAction {
+                x,
+                y,
+                pressure: None,
+            }))
             .unwrap();
     });
 
     let sender_gesture_motion = sender.clone();
     gesture.connect_drag_update(move |gesture, x, y| {
-        //gesture.set_state(EventSequenceState::Claimed);
+        if is_stylus(gesture) {
+            return;
+        }
         let (start_x, start_y) = gesture.start_point().unwrap();
         sender_gesture_motion
             .send(Action::MouseMotion(MouseMotionAction {
                 x: x + start_x,
                 y: y + start_y,
+                pressure: None,
             }))
             .unwrap();
     });
     let sender_gesture_up = sender.clone();
     gesture.connect_drag_end(move |gesture, x, y| {
+        if is_stylus(gesture) {
+            return;
+        }
         let (start_x, start_y) = gesture.start_point().unwrap();
         sender_gesture_up
             .send(Action::MouseRelease(MouseReleaseAction {
                 x: x + start_x,
                 y: y + start_y,
+                pressure: None,
             }))
             .unwrap();
     });
@@ -184,7 +1114,7 @@ fn build_ui(app: &Application) {
     widget.add_controller(&scroll_controller);
 
     let motion_controller = EventControllerMotion::new();
-    let sender_motion = sender;
+    let sender_motion = sender.clone();
     motion_controller.connect_motion(move |_, x, y| {
         sender_motion
             .send(Action::Motion(MotionEvent { x, y }))
@@ -192,6 +1122,20 @@ fn build_ui(app: &Application) {
     });
     widget.add_controller(&motion_controller);
 
+    // shape modifiers have to be tracked on the window, the drawing area never has key focus
+    let key_controller = EventControllerKey::new();
+    let sender_modifiers = sender.clone();
+    key_controller.connect_modifiers(move |_, modifiers| {
+        sender_modifiers
+            .send(Action::ShapeModifiers(ShapeModifiers {
+                constrain: modifiers.contains(ModifierType::SHIFT_MASK),
+                centered: modifiers.contains(ModifierType::CONTROL_MASK),
+            }))
+            .unwrap();
+        Inhibit(false)
+    });
+    window.add_controller(&key_controller);
+
     let click_controller = GestureClick::new();
     click_controller.set_button(BUTTON_SECONDARY);
     click_controller.connect_pressed(move |_, _, x, y| {
@@ -209,27 +1153,37 @@ fn build_ui(app: &Application) {
     let mut widgets = Widgets {
         widget: widget.clone(),
         pipeline: frame_sender,
+        text_view,
+        text_css,
+        text_position: None,
+        text_scale: 1.0,
+        edit_actions: vec![
+            undo_action,
+            redo_action,
+            cut_action,
+            copy_action,
+            paste_action,
+        ],
+        tool_action,
+        layout_action,
+        layer_panel,
     };
-    let state = Rc::new(RefCell::new(AppState {
-        drawing: RTree::new(),
-        stroke: None,
-        viewport: Viewport {
-            width: 0,
-            height: 0,
-            transform: Transform2D::identity(),
-        },
-        scroll_state: None,
-        pointer_old: None,
-        tool: Tool::Pen,
-    }));
     widget.queue_draw();
     widgets.update(&state.borrow());
+    let kinetic_sender = sender;
     receiver.attach(None, move |action| {
+        let continues_kinetic = matches!(action, Action::MouseRelease(_) | Action::KineticScroll);
         update(action, &mut widgets, &mut state.borrow_mut());
+        if continues_kinetic && state.borrow().kinetic.is_some() {
+            let sender = kinetic_sender.clone();
+            glib::timeout_add_local_once(KINETIC_INTERVAL, move || {
+                sender.send(Action::KineticScroll).unwrap();
+            });
+        }
         Continue(true)
     });
 
-    window.set_child(Some(&widget));
+    window.set_child(Some(&overlay));
     window.set_default_width(600);
     window.set_default_height(600);
     window.set_title(Some("XournalR"));
@@ -238,5 +1192,127 @@ fn build_ui(app: &Application) {
 
 fn update(action: Action, widgets: &mut Widgets, state: &mut AppState) {
     state.dispatch(action);
+    if let Some(elements) = state.copied.take() {
+        clipboard::copy(&widgets.widget.clipboard(), &elements);
+    }
     widgets.update(state);
 }
+
+/// whether the gesture is driven by a pen or stylus device
+fn is_stylus(gesture: &impl IsA<gtk::Gesture>) -> bool {
+    gesture
+        .device()
+        .map_or(false, |device| device.source() == InputSource::Pen)
+}
+
+/// show a file chooser and send the action built from the chosen path
+fn choose_file(
+    window: &ApplicationWindow,
+    title: &str,
+    chooser_action: FileChooserAction,
+    filter: (&str, &[&str]),
+    sender: SyncSender<Action>,
+    on_chosen: impl Fn(PathBuf) -> Action + 'static,
+) {
+    let dialog = FileChooserNative::new(Some(title), Some(window), chooser_action, None, None);
+    let (filter_name, extensions) = filter;
+    let file_filter = FileFilter::new();
+    file_filter.set_name(Some(filter_name));
+    for extension in extensions {
+        file_filter.add_pattern(&format!("*.{}", extension));
+    }
+    dialog.add_filter(&file_filter);
+    // the dialog has to stay alive until it responds
+    let dialog_handle = RefCell::new(Some(dialog.clone()));
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Accept {
+            if let Some(path) = dialog.file().and_then(|file| file.path()) {
+                sender.send(on_chosen(path)).unwrap();
+            }
+        }
+        dialog_handle.borrow_mut().take();
+    });
+    dialog.show();
+}
+
+/// ask for the width and height of a custom page size in millimetres and send it as
+/// `Action::PageSize`
+fn choose_page_size(window: &ApplicationWindow, current: PaperSize, sender: SyncSender<Action>) {
+    let dialog = Dialog::with_buttons(
+        Some("Page Size"),
+        Some(window),
+        DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT,
+        &[("Cancel", ResponseType::Cancel), ("OK", ResponseType::Ok)],
+    );
+    let (width, height) = current.dimensions();
+    let millimetres = |units: f64| units / UNITS_PER_INCH * MM_PER_INCH;
+    let width_button = SpinButton::with_range(10.0, 2000.0, 1.0);
+    width_button.set_value(millimetres(width).round());
+    let height_button = SpinButton::with_range(10.0, 2000.0, 1.0);
+    height_button.set_value(millimetres(height).round());
+    let grid = Grid::new();
+    grid.set_row_spacing(6);
+    grid.set_column_spacing(12);
+    grid.set_margin_top(12);
+    grid.set_margin_bottom(12);
+    grid.set_margin_start(12);
+    grid.set_margin_end(12);
+    grid.attach(&Label::new(Some("Width (mm)")), 0, 0, 1, 1);
+    grid.attach(&width_button, 1, 0, 1, 1);
+    grid.attach(&Label::new(Some("Height (mm)")), 0, 1, 1, 1);
+    grid.attach(&height_button, 1, 1, 1, 1);
+    dialog.content_area().append(&grid);
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Ok {
+            let units = |mm: f64| mm / MM_PER_INCH * UNITS_PER_INCH;
+            let size = PaperSize::Custom(units(width_button.value()), units(height_button.value()));
+            sender.send(Action::PageSize(size)).unwrap();
+        }
+        dialog.destroy();
+    });
+    dialog.show();
+}
+
+/// ask for the first and last page to export, then for the PDF file to export them to
+fn choose_page_range(window: &ApplicationWindow, page_count: usize, sender: SyncSender<Action>) {
+    let dialog = Dialog::with_buttons(
+        Some("Export Pages"),
+        Some(window),
+        DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT,
+        &[("Cancel", ResponseType::Cancel), ("OK", ResponseType::Ok)],
+    );
+    let from_button = SpinButton::with_range(1.0, page_count as f64, 1.0);
+    from_button.set_value(1.0);
+    let to_button = SpinButton::with_range(1.0, page_count as f64, 1.0);
+    to_button.set_value(page_count as f64);
+    let grid = Grid::new();
+    grid.set_row_spacing(6);
+    grid.set_column_spacing(12);
+    grid.set_margin_top(12);
+    grid.set_margin_bottom(12);
+    grid.set_margin_start(12);
+    grid.set_margin_end(12);
+    grid.attach(&Label::new(Some("From page")), 0, 0, 1, 1);
+    grid.attach(&from_button, 1, 0, 1, 1);
+    grid.attach(&Label::new(Some("To page")), 0, 1, 1, 1);
+    grid.attach(&to_button, 1, 1, 1, 1);
+    dialog.content_area().append(&grid);
+    let window = window.clone();
+    dialog.connect_response(move |dialog, response| {
+        if response == ResponseType::Ok {
+            let from = from_button.value_as_int() as usize;
+            let to = (to_button.value_as_int() as usize).max(from);
+            let range = from - 1..to;
+            choose_file(
+                &window,
+                "Export as PDF",
+                FileChooserAction::Save,
+                PDF_FILTER,
+                sender.clone(),
+                move |path| Action::ExportPdf(path, Some(range.clone())),
+            );
+        }
+        dialog.destroy();
+    });
+    dialog.show();
+}
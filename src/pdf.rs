@@ -0,0 +1,168 @@
+use std::cell::RefCell;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use gtk::cairo::{Context, Format, ImageSurface, SurfaceType};
+use gtk::glib;
+use poppler::{PopplerDocument, PopplerPage};
+use serde::{Deserialize, Serialize};
+
+use crate::export::PaperSize;
+use crate::quadtree::{Page, Viewport};
+
+pub const FILE_EXTENSION: &str = "pdf";
+/// highest number of pixels per document unit PDF pages are rendered with on screen, deeper zoom
+/// scales up the rendered image instead of rendering ever larger ones
+const MAX_RASTER_SCALE: f64 = 4.0;
+/// memory kept for rendered PDF pages, enough for the pages on screen and a few around them
+const MAX_CACHED_BYTES: usize = 128 * 1024 * 1024;
+
+/// page of a PDF file shown as the background of a page, the file is read when it is first drawn
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PdfBackground {
+    pub path: PathBuf,
+    /// index of the page in the PDF file
+    pub page: usize,
+}
+
+impl PdfBackground {
+    /// render the PDF page at the top left corner of the page, `viewport` maps onto page
+    /// coordinates
+    ///
+    /// PDF and SVG export keep the content as vector graphics with the original text and
+    /// drawings, everything else draws an image rendered once per zoom level.
+    pub fn draw(&self, cairo_context: &Context, viewport: &Viewport) {
+        let page = match document(&self.path).and_then(|document| document.get_page(self.page)) {
+            Some(page) => page,
+            None => return,
+        };
+        let scale = viewport.transform.m11;
+        let (x, y) = viewport.transform_to_viewport((0.0, 0.0));
+        cairo_context.save().unwrap();
+        cairo_context.translate(x, y);
+        cairo_context.scale(1.0 / scale, 1.0 / scale);
+        match cairo_context.target().type_() {
+            SurfaceType::Pdf | SurfaceType::Svg => page.render_for_printing(cairo_context),
+            _ => {
+                let raster_scale = (1.0 / scale).min(MAX_RASTER_SCALE);
+                if let Some(surface) = self.surface(&page, raster_scale) {
+                    cairo_context.scale(1.0 / raster_scale, 1.0 / raster_scale);
+                    cairo_context
+                        .set_source_surface(&surface, 0.0, 0.0)
+                        .unwrap();
+                    cairo_context.paint().unwrap();
+                }
+            }
+        }
+        cairo_context.restore().unwrap();
+    }
+
+    /// `page` rendered with `raster_scale` pixels per document unit
+    ///
+    /// Only the last rendering of every page is kept, the least recently drawn pages are dropped
+    /// once they take more than `MAX_CACHED_BYTES`.
+    fn surface(&self, page: &PopplerPage, raster_scale: f64) -> Option<ImageSurface> {
+        SURFACES.with(|surfaces| {
+            let mut surfaces = surfaces.borrow_mut();
+            let cached = surfaces
+                .iter()
+                .position(|(background, _, _)| background == self);
+            if let Some(index) = cached {
+                let entry = surfaces.remove(index);
+                if entry.1 == raster_scale {
+                    let surface = entry.2.clone();
+                    // most recently drawn last
+                    surfaces.push(entry);
+                    return Some(surface);
+                }
+            }
+            let (width, height) = page.get_size();
+            let surface = match ImageSurface::create(
+                Format::ARgb32,
+                (width * raster_scale).ceil() as i32,
+                (height * raster_scale).ceil() as i32,
+            ) {
+                Ok(surface) => surface,
+                Err(err) => {
+                    log::warn!("Failed to render {}: {}", self.path.display(), err);
+                    return None;
+                }
+            };
+            let cairo_context = Context::new(&surface).ok()?;
+            cairo_context.scale(raster_scale, raster_scale);
+            page.render(&cairo_context);
+            surfaces.push((self.clone(), raster_scale, surface.clone()));
+            let bytes =
+                |surface: &ImageSurface| 4 * surface.width() as usize * surface.height() as usize;
+            let mut total = surfaces
+                .iter()
+                .map(|(_, _, surface)| bytes(surface))
+                .sum::<usize>();
+            // the page just rendered stays even if it alone is larger
+            while total > MAX_CACHED_BYTES && surfaces.len() > 1 {
+                let (_, _, dropped) = surfaces.remove(0);
+                total -= bytes(&dropped);
+            }
+            Some(surface)
+        })
+    }
+}
+
+/// one page for every page of the PDF file at `path`, sized like it and showing it as background
+pub fn load(path: &Path) -> Result<Vec<Page>, glib::Error> {
+    let document = PopplerDocument::new_from_file(path, "")?;
+    let mut pages = (0..document.get_n_pages())
+        .filter_map(|index| {
+            let (width, height) = document.get_page(index)?.get_size();
+            Some(Page {
+                pdf: Some(PdfBackground {
+                    path: path.to_owned(),
+                    page: index,
+                }),
+                ..Page::new(PaperSize::from_dimensions(width, height))
+            })
+        })
+        .collect::<Vec<_>>();
+    if pages.is_empty() {
+        pages.push(Page::new(PaperSize::A4));
+    }
+    DOCUMENTS.with(|documents| {
+        let mut documents = documents.borrow_mut();
+        documents.retain(|(cached, _)| cached != path);
+        documents.push((path.to_owned(), Some(Rc::new(document))));
+    });
+    // the file may have changed since its pages were last rendered
+    SURFACES.with(|surfaces| {
+        surfaces
+            .borrow_mut()
+            .retain(|(background, _, _)| background.path != path)
+    });
+    Ok(pages)
+}
+
+thread_local! {
+    /// PDF files read so far, `None` if reading failed so it is not retried on every frame
+    static DOCUMENTS: RefCell<Vec<(PathBuf, Option<Rc<PopplerDocument>>)>> = RefCell::new(Vec::new());
+    /// PDF pages rendered for the screen with the number of pixels per document unit, least
+    /// recently drawn first
+    static SURFACES: RefCell<Vec<(PdfBackground, f64, ImageSurface)>> = RefCell::new(Vec::new());
+}
+
+/// the PDF file at `path`, read on first use
+fn document(path: &Path) -> Option<Rc<PopplerDocument>> {
+    DOCUMENTS.with(|documents| {
+        let mut documents = documents.borrow_mut();
+        if let Some((_, document)) = documents.iter().find(|(cached, _)| cached == path) {
+            return document.clone();
+        }
+        let document = match PopplerDocument::new_from_file(path, "") {
+            Ok(document) => Some(Rc::new(document)),
+            Err(err) => {
+                log::warn!("Failed to read {}: {}", path.display(), err);
+                None
+            }
+        };
+        documents.push((path.to_owned(), document.clone()));
+        document
+    })
+}
@@ -1,8 +1,17 @@
 use euclid::{default::Point2D, default::Transform2D};
-use geo::{LineString, Point};
-use gtk::cairo::LineCap;
+use geo::algorithm::intersects::Intersects;
+use geo::{LineString, Point, Rect};
 use gtk::cairo::{Context, LineJoin};
-use rstar::{PointDistance, RTree, AABB};
+use gtk::cairo::{LineCap, Operator};
+use rstar::{Envelope, PointDistance, RTree, RTreeObject, AABB};
+use serde::{Deserialize, Serialize};
+
+use crate::background::Background;
+use crate::export::PaperSize;
+use crate::history::Change;
+use crate::image::Image;
+use crate::pdf::PdfBackground;
+use crate::text::TextBox;
 
 #[derive(Clone)]
 pub struct Viewport {
@@ -11,108 +20,742 @@ pub struct Viewport {
     pub transform: Transform2D<f64>,
 }
 
-pub trait Document {
-    fn add(&mut self, stroke: LineString<f64>, viewport: &Viewport);
-    fn elements_in_viewport<'a>(
-        &'a self,
-        viewport: &Viewport,
-    ) -> Box<dyn Iterator<Item = &'a LineString<f64>> + 'a>;
-    fn elements_in_viewport_mut<'a>(
-        &'a mut self,
-        viewport: &Viewport,
-    ) -> Box<dyn Iterator<Item = &'a mut LineString<f64>> + 'a>;
+/// vertical space between pages in document units
+pub const PAGE_GAP: f64 = 20.0;
+
+/// sheet of paper, its elements are stored relative to its top left corner
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Page {
+    pub size: PaperSize,
+    pub background: Background,
+    /// drawn on top of `background`, below every layer
+    pub pdf: Option<PdfBackground>,
+    /// bottom layer first, never empty
+    pub layers: Vec<Layer>,
+    /// index of the layer tools act on
+    pub layer: usize,
+}
+
+impl Page {
+    pub fn new(size: PaperSize) -> Self {
+        Page {
+            size,
+            background: Background::default(),
+            pdf: None,
+            layers: vec![Layer::new(Layer::default_name(0))],
+            layer: 0,
+        }
+    }
+
+    pub fn active_layer(&self) -> &Layer {
+        &self.layers[self.layer]
+    }
+
+    pub fn active_layer_mut(&mut self) -> &mut Layer {
+        &mut self.layers[self.layer]
+    }
+
+    /// bounding box of the elements on all layers, `None` if there are none
+    pub fn content_envelope(&self) -> Option<AABB<Point<f64>>> {
+        self.layers
+            .iter()
+            .filter(|layer| layer.elements.size() > 0)
+            .map(|layer| layer.elements.root().envelope())
+            .reduce(|a, b| a.merged(&b))
+    }
+
+    /// fill the page with its background and PDF page, `viewport` maps onto page coordinates
+    pub fn draw_paper(&self, cairo_context: &Context, viewport: &Viewport) {
+        self.background
+            .draw(cairo_context, viewport, self.size.dimensions());
+        if let Some(pdf) = &self.pdf {
+            pdf.draw(cairo_context, viewport);
+        }
+    }
+
+    /// draw the visible layers bottom to top, `viewport` maps onto page coordinates
+    pub fn draw(&self, cairo_context: &Context, viewport: &Viewport) {
+        for layer in self.layers.iter().filter(|layer| layer.visible) {
+            layer.draw(cairo_context, viewport);
+        }
+    }
+
+    /// erase on the active layer of this page, which has index `page`, in page coordinates
+    fn erase(
+        &mut self,
+        page: usize,
+        start: (f64, f64),
+        end: (f64, f64),
+        radius: f64,
+    ) -> Vec<Change> {
+        let layer = self.layer;
+        if !self.layers[layer].editable() {
+            return Vec::new();
+        }
+        self.layers[layer].erase(page, layer, start, end, radius)
+    }
+}
+
+/// named set of elements on a page, layers further up are drawn on top
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    /// no tool changes the elements of a locked layer
+    pub locked: bool,
+    pub elements: RTree<Element>,
 }
 
-impl Document for RTree<LineString<f64>> {
-    fn add(&mut self, stroke: LineString<f64>, viewport: &Viewport) {
-        let normalized_stroke = stroke.normalize(viewport);
-        self.insert(normalized_stroke);
+impl Layer {
+    pub fn new(name: String) -> Self {
+        Layer {
+            name,
+            visible: true,
+            locked: false,
+            elements: RTree::new(),
+        }
+    }
+
+    /// name of a new layer added at `index`
+    pub fn default_name(index: usize) -> String {
+        format!("Layer {}", index + 1)
+    }
+
+    /// whether tools may change the elements, hidden layers cannot be seen changing
+    pub fn editable(&self) -> bool {
+        self.visible && !self.locked
     }
 
-    fn elements_in_viewport<'a>(
+    /// `viewport` maps onto page coordinates
+    pub fn elements_in_viewport<'a>(
         &'a self,
         viewport: &Viewport,
-    ) -> Box<dyn Iterator<Item = &'a LineString<f64>> + 'a> {
-        Box::new(self.locate_in_envelope_intersecting(&viewport.normalized()))
-            as Box<dyn Iterator<Item = &LineString<f64>>>
+    ) -> impl Iterator<Item = &'a Element> + 'a {
+        self.elements
+            .locate_in_envelope_intersecting(&viewport.normalized())
     }
 
-    fn elements_in_viewport_mut<'a>(
+    pub fn elements_in_viewport_mut<'a>(
         &'a mut self,
         viewport: &Viewport,
-    ) -> Box<dyn Iterator<Item = &'a mut LineString<f64>> + 'a> {
-        Box::new(self.locate_in_envelope_intersecting_mut(&viewport.normalized()))
-            as Box<dyn Iterator<Item = &mut LineString<f64>>>
+    ) -> impl Iterator<Item = &'a mut Element> + 'a {
+        self.elements
+            .locate_in_envelope_intersecting_mut(&viewport.normalized())
+    }
+
+    /// draw all elements visible in `viewport`, which maps onto page coordinates
+    pub fn draw(&self, cairo_context: &Context, viewport: &Viewport) {
+        // highlighters go underneath so ink drawn on top keeps its colour
+        let (highlighters, others): (Vec<_>, Vec<_>) = self
+            .elements_in_viewport(viewport)
+            .partition(|elem| {
+                matches!(elem, Element::Stroke(ink) if ink.style.kind == StrokeKind::Highlighter)
+            });
+        for elem in highlighters.into_iter().chain(others) {
+            elem.draw(cairo_context, viewport);
+        }
+    }
+
+    /// erase on this layer, which has index `layer` on page `page`, in page coordinates
+    fn erase(
+        &mut self,
+        page: usize,
+        layer: usize,
+        start: (f64, f64),
+        end: (f64, f64),
+        radius: f64,
+    ) -> Vec<Change> {
+        let envelope = AABB::from_corners(
+            (start.0.min(end.0) - radius, start.1.min(end.1) - radius).into(),
+            (start.0.max(end.0) + radius, start.1.max(end.1) + radius).into(),
+        );
+        // sample the eraser path densely enough that no stroke can slip between two points
+        let length = ((end.0 - start.0).powi(2) + (end.1 - start.1).powi(2)).sqrt();
+        let steps = (length / radius.max(f64::EPSILON)).ceil().max(1f64) as usize;
+        let points = (0..=steps)
+            .map(|i| {
+                let t = i as f64 / steps as f64;
+                (
+                    start.0 + (end.0 - start.0) * t,
+                    start.1 + (end.1 - start.1) * t,
+                )
+            })
+            .collect::<Vec<_>>();
+        let elements = self
+            .elements
+            .drain_in_envelope_intersecting(envelope)
+            .collect::<Vec<_>>();
+        let mut changes = Vec::new();
+        for element in elements {
+            let ink = match &element {
+                Element::Stroke(ink) => ink,
+                Element::Text(_) | Element::Image(_) => {
+                    let distance_2 = radius * radius;
+                    let element_envelope = element.envelope();
+                    if points
+                        .iter()
+                        .any(|p| element_envelope.distance_2(&(*p).into()) <= distance_2)
+                    {
+                        changes.push(Change::Remove(page, layer, element));
+                    } else {
+                        self.elements.insert(element);
+                    }
+                    continue;
+                }
+            };
+            let mut fragments = vec![ink.clone()];
+            for point in &points {
+                fragments = fragments
+                    .into_iter()
+                    .flat_map(|fragment| fragment.erase_point(*point, radius))
+                    .collect();
+            }
+            if fragments.len() == 1 && &fragments[0] == ink {
+                self.elements.insert(element);
+                continue;
+            }
+            changes.push(Change::Remove(page, layer, element));
+            for fragment in fragments {
+                let fragment = Element::Stroke(fragment);
+                changes.push(Change::Insert(page, layer, fragment.clone()));
+                self.elements.insert(fragment);
+            }
+        }
+        changes
     }
 }
 
+/// pages laid out top to bottom with their left edges at zero
+pub trait Document {
+    /// top left corner of page `index` in document coordinates
+    fn page_origin(&self, index: usize) -> (f64, f64);
+    /// area covered by page `index` in document coordinates
+    fn page_envelope(&self, index: usize) -> AABB<Point<f64>>;
+    /// page under `point` in document coordinates, the closest page if there is none
+    fn page_at(&self, point: (f64, f64)) -> usize;
+    /// indices of the pages whose paper or elements overlap the viewport
+    fn pages_in_viewport(&self, viewport: &Viewport) -> Vec<usize>;
+    /// `viewport` changed to map onto the coordinates of page `index`
+    fn page_viewport(&self, index: usize, viewport: &Viewport) -> Viewport;
+    /// bounding box of all pages in document coordinates
+    fn envelope(&self) -> AABB<Point<f64>>;
+    /// erase everything on the active layers within `radius` of the segment from `start` to `end`
+    /// in document coordinates
+    ///
+    /// Strokes are split, text boxes and images touched by the eraser are removed as a whole.
+    fn erase(&mut self, start: (f64, f64), end: (f64, f64), radius: f64) -> Vec<Change>;
+    /// fill the visible pages with paper
+    fn draw_paper(&self, cairo_context: &Context, viewport: &Viewport);
+    /// draw the elements of all visible pages
+    fn draw(&self, cairo_context: &Context, viewport: &Viewport);
+}
+
+impl Document for [Page] {
+    fn page_origin(&self, index: usize) -> (f64, f64) {
+        let y = self[..index]
+            .iter()
+            .map(|page| page.size.dimensions().1 + PAGE_GAP)
+            .sum();
+        (0.0, y)
+    }
+
+    fn page_envelope(&self, index: usize) -> AABB<Point<f64>> {
+        let (x, y) = self.page_origin(index);
+        let (width, height) = self[index].size.dimensions();
+        AABB::from_corners((x, y).into(), (x + width, y + height).into())
+    }
+
+    fn page_at(&self, point: (f64, f64)) -> usize {
+        let mut top = 0.0;
+        for (index, page) in self.iter().enumerate() {
+            let bottom = top + page.size.dimensions().1;
+            // the gap below a page still counts to it
+            if point.1 < bottom + PAGE_GAP {
+                return index;
+            }
+            top = bottom + PAGE_GAP;
+        }
+        self.len().saturating_sub(1)
+    }
+
+    fn pages_in_viewport(&self, viewport: &Viewport) -> Vec<usize> {
+        let visible = viewport.normalized();
+        layout(self)
+            .filter(|&(index, (x, y))| {
+                let page = &self[index];
+                let (width, height) = page.size.dimensions();
+                let mut envelope = AABB::from_corners((0.0, 0.0).into(), (width, height).into());
+                if let Some(content) = page.content_envelope() {
+                    envelope.merge(&content);
+                }
+                let (lower, upper) = (envelope.lower(), envelope.upper());
+                AABB::from_corners(
+                    (lower.x() + x, lower.y() + y).into(),
+                    (upper.x() + x, upper.y() + y).into(),
+                )
+                .intersects(&visible)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    fn page_viewport(&self, index: usize, viewport: &Viewport) -> Viewport {
+        let (x, y) = self.page_origin(index);
+        Viewport {
+            transform: viewport.transform.then_translate((-x, -y).into()),
+            ..viewport.clone()
+        }
+    }
+
+    fn envelope(&self) -> AABB<Point<f64>> {
+        let width = self
+            .iter()
+            .map(|page| page.size.dimensions().0)
+            .fold(0.0, f64::max);
+        let height = self.page_origin(self.len()).1 - PAGE_GAP;
+        AABB::from_corners((0.0, 0.0).into(), (width, height.max(0.0)).into())
+    }
+
+    fn erase(&mut self, start: (f64, f64), end: (f64, f64), radius: f64) -> Vec<Change> {
+        let origins = layout(self).collect::<Vec<_>>();
+        let mut changes = Vec::new();
+        for (index, (x, y)) in origins {
+            changes.extend(self[index].erase(
+                index,
+                (start.0 - x, start.1 - y),
+                (end.0 - x, end.1 - y),
+                radius,
+            ));
+        }
+        changes
+    }
+
+    fn draw_paper(&self, cairo_context: &Context, viewport: &Viewport) {
+        for index in self.pages_in_viewport(viewport) {
+            self[index].draw_paper(cairo_context, &self.page_viewport(index, viewport));
+        }
+    }
+
+    fn draw(&self, cairo_context: &Context, viewport: &Viewport) {
+        for index in self.pages_in_viewport(viewport) {
+            self[index].draw(cairo_context, &self.page_viewport(index, viewport));
+        }
+    }
+}
+
+/// index and top left corner of every page
+fn layout(pages: &[Page]) -> impl Iterator<Item = (usize, (f64, f64))> + '_ {
+    pages.iter().enumerate().scan(0.0, |top, (index, page)| {
+        let origin = (0.0, *top);
+        *top += page.size.dimensions().1 + PAGE_GAP;
+        Some((index, origin))
+    })
+}
+
+/// anything that can be placed in the document
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Element {
+    Stroke(Ink),
+    Text(TextBox),
+    Image(Image),
+}
+
+impl Element {
+    pub fn draw(&self, cairo_context: &Context, viewport: &Viewport) {
+        match self {
+            Element::Stroke(ink) => ink.draw(cairo_context, viewport),
+            Element::Text(text) => text.draw(cairo_context, viewport),
+            Element::Image(image) => image.draw(cairo_context, viewport),
+        }
+    }
+
+    /// copy of the element with `transform` applied to its document coordinates
+    ///
+    /// Line widths and font sizes follow the average scale, text and images stay upright when
+    /// rotated.
+    pub fn transformed(&self, transform: &Transform2D<f64>) -> Element {
+        let scale = transform.determinant().abs().sqrt();
+        let apply =
+            |point: (f64, f64)| -> (f64, f64) { transform.transform_point(point.into()).into() };
+        match self {
+            Element::Stroke(ink) => {
+                let mut ink = ink.clone();
+                for c in &mut ink.line.0 {
+                    *c = apply((c.x, c.y)).into();
+                }
+                ink.style.width *= scale;
+                Element::Stroke(ink)
+            }
+            Element::Text(text) => {
+                let mut style = text.style.clone();
+                style.size *= scale;
+                Element::Text(TextBox::new(apply(text.position), text.text.clone(), style))
+            }
+            Element::Image(image) => {
+                let mut image = image.clone();
+                let (x, y) = image.position;
+                let (width, height) = image.size;
+                if transform.m12 == 0.0 && transform.m21 == 0.0 {
+                    let (a, b) = (apply((x, y)), apply((x + width, y + height)));
+                    image.position = (a.0.min(b.0), a.1.min(b.1));
+                    image.size = ((b.0 - a.0).abs(), (b.1 - a.1).abs());
+                } else {
+                    let center = apply((x + width / 2.0, y + height / 2.0));
+                    image.size = (width * scale, height * scale);
+                    image.position = (center.0 - image.size.0 / 2.0, center.1 - image.size.1 / 2.0);
+                }
+                Element::Image(image)
+            }
+        }
+    }
+
+    /// whether a line in document coordinates touches the element
+    pub fn intersects(&self, line: &LineString<f64>) -> bool {
+        match self {
+            Element::Stroke(ink) => line.intersects(&ink.line),
+            Element::Text(_) | Element::Image(_) => {
+                let envelope = self.envelope();
+                let rect = Rect::new(envelope.lower().0, envelope.upper().0);
+                rect.to_polygon().intersects(line)
+            }
+        }
+    }
+}
+
+impl RTreeObject for Element {
+    type Envelope = AABB<Point<f64>>;
+
+    fn envelope(&self) -> Self::Envelope {
+        match self {
+            Element::Stroke(ink) => ink.envelope(),
+            Element::Text(text) => text.envelope(),
+            Element::Image(image) => image.envelope(),
+        }
+    }
+}
+
+/// default line width in document units
+pub const LINE_WIDTH: f64 = 2.0;
+/// thinnest pressure sensitive stroke relative to its nominal width
+pub const MIN_PRESSURE_WIDTH: f64 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rgba {
+    pub red: f64,
+    pub green: f64,
+    pub blue: f64,
+    pub alpha: f64,
+}
+
+impl Rgba {
+    pub const fn new(red: f64, green: f64, blue: f64, alpha: f64) -> Self {
+        Rgba {
+            red,
+            green,
+            blue,
+            alpha,
+        }
+    }
+
+    /// parse `#rrggbb` or `#rrggbbaa`
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#')?;
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| {
+            hex.get(i..i + 2)
+                .map(|c| u8::from_str_radix(c, 16).ok().map(|c| c as f64 / 255.0))
+        };
+        Some(Rgba {
+            red: channel(0)??,
+            green: channel(2)??,
+            blue: channel(4)??,
+            alpha: channel(6).unwrap_or(Some(1.0))?,
+        })
+    }
+
+    /// format as `#rrggbbaa`
+    pub fn to_hex(self) -> String {
+        let channel = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        format!(
+            "#{:02x}{:02x}{:02x}{:02x}",
+            channel(self.red),
+            channel(self.green),
+            channel(self.blue),
+            channel(self.alpha)
+        )
+    }
+}
+
+/// how a stroke is blended with what lies beneath it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrokeKind {
+    Pen,
+    /// constant width, multiplied onto the page so underlying ink stays readable
+    Highlighter,
+}
+
+/// appearance of a stroke, also used as the pen settings new strokes inherit
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StrokeStyle {
+    pub kind: StrokeKind,
+    pub color: Rgba,
+    /// line width in document units
+    pub width: f64,
+    /// opacity of the whole stroke in `0.0..=1.0`
+    pub opacity: f64,
+}
+
+impl Default for Rgba {
+    fn default() -> Self {
+        Rgba::new(0.0, 0.0, 1.0, 1.0)
+    }
+}
+
+impl Default for StrokeStyle {
+    fn default() -> Self {
+        StrokeStyle {
+            kind: StrokeKind::Pen,
+            color: Rgba::default(),
+            width: LINE_WIDTH,
+            opacity: 1.0,
+        }
+    }
+}
+
+/// stroke as stored in the document
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(from = "InkFile")]
+pub struct Ink {
+    pub line: LineString<f64>,
+    /// pen pressure in `0.0..=1.0` for every point, empty if the device reported none
+    pub pressure: Vec<f64>,
+    pub style: StrokeStyle,
+}
+
+/// `Ink` as read from a file, where the pressure may not match the number of points
+#[derive(Deserialize)]
+struct InkFile {
+    line: LineString<f64>,
+    pressure: Vec<f64>,
+    style: StrokeStyle,
+}
+
+impl From<InkFile> for Ink {
+    /// repeat the last pressure for missing points and drop pressure without a point
+    fn from(file: InkFile) -> Self {
+        let mut pressure = file.pressure;
+        if let Some(last) = pressure.last().copied() {
+            pressure.resize(file.line.0.len(), last);
+        }
+        Ink {
+            line: file.line,
+            pressure,
+            style: file.style,
+        }
+    }
+}
+
+impl Ink {
+    pub fn new(style: StrokeStyle) -> Self {
+        Ink {
+            line: LineString(Vec::new()),
+            pressure: Vec::new(),
+            style,
+        }
+    }
+
+    /// copy point `index` of `other` to the end of this stroke
+    fn push_from(&mut self, other: &Ink, index: usize) {
+        self.line.0.push(other.line.0[index]);
+        if let Some(pressure) = other.pressure.get(index) {
+            self.pressure.push(*pressure);
+        }
+    }
+
+    /// draw already transformed points, `scale` is the number of document units per output unit
+    fn trace(&self, cairo_context: &Context, points: &[(f64, f64)], scale: f64) {
+        if points.is_empty() {
+            return;
+        }
+        let color = self.style.color;
+        cairo_context.set_source_rgba(
+            color.red,
+            color.green,
+            color.blue,
+            color.alpha * self.style.opacity,
+        );
+        if self.style.kind == StrokeKind::Highlighter {
+            cairo_context.set_operator(Operator::Multiply);
+        }
+        let width = self.style.width / scale;
+        if self.pressure.is_empty() {
+            let mut iter = points.iter();
+            let first = iter.next().unwrap();
+            cairo_context.set_line_width(width);
+            cairo_context.move_to(first.0, first.1);
+            if points.len() == 1 {
+                // a single point is drawn as a dot by the round caps
+                cairo_context.line_to(first.0, first.1);
+            }
+            for point in iter {
+                cairo_context.line_to(point.0, point.1);
+            }
+            cairo_context.stroke().unwrap();
+        } else {
+            let widths = self
+                .pressure
+                .iter()
+                .map(|p| width * p.max(MIN_PRESSURE_WIDTH))
+                .collect::<Vec<_>>();
+            fill_outline(cairo_context, points, &widths);
+        }
+        cairo_context.set_operator(Operator::Over);
+    }
+}
+
+impl RTreeObject for Ink {
+    type Envelope = AABB<Point<f64>>;
+
+    fn envelope(&self) -> Self::Envelope {
+        let envelope = self.line.envelope();
+        let half = self.style.width / 2.0;
+        let (lower, upper) = (envelope.lower(), envelope.upper());
+        AABB::from_corners(
+            (lower.x() - half, lower.y() - half).into(),
+            (upper.x() + half, upper.y() + half).into(),
+        )
+    }
+}
+
+/// fill the outline of a polyline whose width changes from point to point
+fn fill_outline(cairo_context: &Context, points: &[(f64, f64)], widths: &[f64]) {
+    let n = points.len();
+    let mut left = Vec::with_capacity(n);
+    let mut right = Vec::with_capacity(n);
+    for i in 0..n {
+        let before = points[i.saturating_sub(1)];
+        let after = points[(i + 1).min(n - 1)];
+        let (dx, dy) = (after.0 - before.0, after.1 - before.1);
+        let length = dx.hypot(dy);
+        let (nx, ny) = if length > f64::EPSILON {
+            (-dy / length, dx / length)
+        } else {
+            (0f64, 0f64)
+        };
+        let half = widths[i] / 2.0;
+        left.push((points[i].0 + nx * half, points[i].1 + ny * half));
+        right.push((points[i].0 - nx * half, points[i].1 - ny * half));
+    }
+    cairo_context.move_to(left[0].0, left[0].1);
+    for point in left.iter().skip(1).chain(right.iter().rev()) {
+        cairo_context.line_to(point.0, point.1);
+    }
+    cairo_context.close_path();
+    // round caps
+    for &i in &[0, n - 1] {
+        cairo_context.new_sub_path();
+        cairo_context.arc(
+            points[i].0,
+            points[i].1,
+            widths[i] / 2.0,
+            0.0,
+            2.0 * std::f64::consts::PI,
+        );
+    }
+    cairo_context.fill().unwrap();
+}
+
 pub trait Stroke: Sized {
-    fn add(&mut self, x: f64, y: f64);
+    fn add(&mut self, x: f64, y: f64, pressure: Option<f64>);
     fn draw(&self, cairo_context: &Context, viewport: &Viewport);
     fn draw_direct(&self, cairo_context: &Context);
     fn normalize(self, viewport: &Viewport) -> Self;
     fn erase_point(self, point: (f64, f64), radius: f64) -> Vec<Self>;
 }
 
-impl Stroke for LineString<f64> {
-    fn add(&mut self, x: f64, y: f64) {
-        self.0.push((x, y).into());
+impl Stroke for Ink {
+    fn add(&mut self, x: f64, y: f64, pressure: Option<f64>) {
+        // a repeated point has no direction to draw its width across
+        if self.line.0.last() == Some(&(x, y).into()) {
+            return;
+        }
+        let pressure = pressure.filter(|_| self.style.kind == StrokeKind::Pen);
+        match pressure {
+            Some(pressure) => {
+                // points without pressure before the first one with pressure are drawn at full width
+                self.pressure.resize(self.line.0.len(), 1.0);
+                self.pressure.push(pressure);
+            }
+            None => {
+                if let Some(last) = self.pressure.last().copied() {
+                    self.pressure.push(last);
+                }
+            }
+        }
+        self.line.0.push((x, y).into());
     }
 
     fn draw(&self, cairo_context: &Context, viewport: &Viewport) {
-        cairo_context.set_source_rgb(0f64, 0f64, 255f64);
         cairo_context.set_line_join(LineJoin::Round);
         cairo_context.set_line_cap(LineCap::Round);
-        let mut iter = self.0.iter();
-        let coordinate = iter.next().unwrap();
-        let point_transformed = viewport.transform_to_viewport(*coordinate);
-        cairo_context.move_to(point_transformed.0, point_transformed.1);
-        for coordinate in iter {
-            let point_transformed = viewport.transform_to_viewport(*coordinate);
-            cairo_context.line_to(point_transformed.0, point_transformed.1);
-        }
-        cairo_context.stroke().unwrap();
+        let points = self
+            .line
+            .0
+            .iter()
+            .map(|c| viewport.transform_to_viewport(*c))
+            .collect::<Vec<_>>();
+        self.trace(cairo_context, &points, viewport.transform.m11);
     }
 
     fn draw_direct(&self, cairo_context: &Context) {
         cairo_context.set_line_join(LineJoin::Round);
         cairo_context.set_line_cap(LineCap::Round);
-        let mut iter = self.0.iter();
-        let coordinate = iter.next().unwrap();
-        cairo_context.move_to(coordinate.x, coordinate.y);
-        for coordinate in self.0.iter() {
-            cairo_context.line_to(coordinate.x, coordinate.y);
-        }
-        cairo_context.stroke().unwrap();
+        let points = self.line.0.iter().map(|c| (c.x, c.y)).collect::<Vec<_>>();
+        self.trace(cairo_context, &points, 1.0);
     }
 
+    /// move points and width from viewport into document space
     fn normalize(mut self, viewport: &Viewport) -> Self {
-        for p in &mut self.0 {
+        for p in &mut self.line.0 {
             *p = viewport.normalize_from_viewport(*p).into();
         }
+        self.style.width *= viewport.transform.m11;
         self
     }
 
     fn erase_point(self, point: (f64, f64), radius: f64) -> Vec<Self> {
         let distance_2 = radius * radius;
+        if self.line.0.len() < 2 {
+            let hit = self.line.0.iter().any(|c| {
+                let dx = c.x - point.0;
+                let dy = c.y - point.1;
+                dx * dx + dy * dy <= distance_2
+            });
+            return if hit { Vec::new() } else { vec![self] };
+        }
         let mut result = Vec::new();
-        let mut current_stroke = Vec::new();
-        for line in self.lines() {
+        let mut current_stroke = Ink::new(self.style);
+        for (i, line) in self.line.lines().enumerate() {
             if line
                 .distance_2_if_less_or_equal(&point.into(), distance_2)
                 .is_some()
             {
-                if !current_stroke.is_empty() {
-                    result.push(current_stroke.into());
-                    current_stroke = Vec::new();
+                if !current_stroke.line.0.is_empty() {
+                    result.push(std::mem::replace(&mut current_stroke, Ink::new(self.style)));
                 }
             } else {
-                current_stroke.push(line.start_point());
-                current_stroke.push(line.end_point());
+                if current_stroke.line.0.is_empty() {
+                    current_stroke.push_from(&self, i);
+                }
+                current_stroke.push_from(&self, i + 1);
             }
         }
-        if !current_stroke.is_empty() {
-            result.push(current_stroke.into());
+        if !current_stroke.line.0.is_empty() {
+            result.push(current_stroke);
         }
         result
     }
@@ -140,3 +783,39 @@ impl Viewport {
         AABB::from_corners(lower_t.into(), upper_t.into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ink_with_pressure(pressure: &str) -> Ink {
+        let style = serde_json::to_string(&StrokeStyle::default()).unwrap();
+        let json = format!(
+            r#"{{"line": [{{"x": 0.0, "y": 0.0}}, {{"x": 1.0, "y": 0.0}}, {{"x": 2.0, "y": 0.0}}],
+                "pressure": {}, "style": {}}}"#,
+            pressure, style
+        );
+        serde_json::from_str(&json).unwrap()
+    }
+
+    #[test]
+    fn loading_pads_missing_pressure_with_the_last_value() {
+        assert_eq!(
+            ink_with_pressure("[0.2, 0.6]").pressure,
+            vec![0.2, 0.6, 0.6]
+        );
+    }
+
+    #[test]
+    fn loading_drops_pressure_without_a_point() {
+        assert_eq!(
+            ink_with_pressure("[0.2, 0.4, 0.6, 0.8]").pressure,
+            vec![0.2, 0.4, 0.6]
+        );
+    }
+
+    #[test]
+    fn loading_keeps_strokes_without_pressure() {
+        assert!(ink_with_pressure("[]").pressure.is_empty());
+    }
+}
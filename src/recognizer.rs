@@ -0,0 +1,356 @@
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use geo::algorithm::simplify::Simplify;
+use geo::LineString;
+
+use crate::shapes::{Shape, ShapeDrag, ShapeModifiers};
+
+/// default largest deviation from the ideal shape, relative to the stroke size
+pub const DEFAULT_TOLERANCE: f64 = 0.15;
+/// tolerance that only accepts carefully drawn shapes
+pub const STRICT_TOLERANCE: f64 = 0.1;
+/// tolerance that also accepts sloppily drawn shapes
+pub const LOOSE_TOLERANCE: f64 = 0.2;
+/// direction changes flatter than this are not treated as corners
+const MIN_CORNER_ANGLE: f64 = PI / 6.0;
+
+type Point = (f64, f64);
+
+/// replace a hand drawn stroke by the shape it resembles, `None` if it resembles none
+///
+/// `tolerance` is the largest deviation from the ideal shape relative to the size of the stroke.
+/// Recognized are lines, arrows drawn in one go, triangles, rectangles and circles.
+pub fn recognize(line: &LineString<f64>, tolerance: f64) -> Option<LineString<f64>> {
+    let points = to_points(line);
+    let (first, last) = (*points.first()?, *points.last()?);
+    let size = diagonal(&points);
+    if size <= f64::EPSILON {
+        return None;
+    }
+    let simplified = to_points(&line.simplify(&(tolerance * size / 2.0)));
+    if distance(first, last) <= tolerance * size {
+        if let Some((center, radius)) = fit_circle(&points, tolerance) {
+            let drag = ShapeDrag {
+                shape: Shape::Ellipse,
+                start: (center.0 - radius, center.1 - radius),
+                end: (center.0 + radius, center.1 + radius),
+            };
+            return Some(drag.outline(ShapeModifiers::default()));
+        }
+        let corners = corners(&simplified[..simplified.len() - 1]);
+        match corners.len() {
+            3 => Some(close(corners)),
+            4 => rectangle(&corners, tolerance).map(close),
+            _ => None,
+        }
+    } else {
+        match simplified.len() {
+            2 => Some(vec![first, last].into()),
+            5 => arrow(&simplified, tolerance),
+            _ => None,
+        }
+    }
+}
+
+fn to_points(line: &LineString<f64>) -> Vec<Point> {
+    line.0.iter().map(|c| (c.x, c.y)).collect()
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    (b.0 - a.0).hypot(b.1 - a.1)
+}
+
+/// direction of the vector from `a` to `b`
+fn direction(a: Point, b: Point) -> f64 {
+    (b.1 - a.1).atan2(b.0 - a.0)
+}
+
+/// wrap an angle into `-PI..=PI`
+fn wrap(angle: f64) -> f64 {
+    let angle = angle.rem_euclid(2.0 * PI);
+    if angle > PI {
+        angle - 2.0 * PI
+    } else {
+        angle
+    }
+}
+
+/// length of the bounding box diagonal
+fn diagonal(points: &[Point]) -> f64 {
+    let (mut lower, mut upper) = ((f64::MAX, f64::MAX), (f64::MIN, f64::MIN));
+    for p in points {
+        lower = (lower.0.min(p.0), lower.1.min(p.1));
+        upper = (upper.0.max(p.0), upper.1.max(p.1));
+    }
+    distance(lower, upper)
+}
+
+/// centre and radius if all points lie close enough to a common circle
+fn fit_circle(points: &[Point], tolerance: f64) -> Option<(Point, f64)> {
+    let n = points.len() as f64;
+    let center = points
+        .iter()
+        .fold((0.0, 0.0), |sum, p| (sum.0 + p.0 / n, sum.1 + p.1 / n));
+    let radius = points.iter().map(|p| distance(center, *p)).sum::<f64>() / n;
+    let deviation = points
+        .iter()
+        .map(|p| (distance(center, *p) - radius).abs())
+        .fold(0.0, f64::max);
+    if deviation <= tolerance * radius {
+        Some((center, radius))
+    } else {
+        None
+    }
+}
+
+/// vertices of a closed polygon without the ones lying on a (nearly) straight edge
+fn corners(polygon: &[Point]) -> Vec<Point> {
+    let mut corners = polygon.to_vec();
+    while corners.len() >= 3 {
+        let n = corners.len();
+        let flat = (0..n).find(|&i| {
+            let (before, at, after) = (corners[(i + n - 1) % n], corners[i], corners[(i + 1) % n]);
+            wrap(direction(at, after) - direction(before, at)).abs() < MIN_CORNER_ANGLE
+        });
+        match flat {
+            Some(i) => {
+                corners.remove(i);
+            }
+            None => break,
+        }
+    }
+    corners
+}
+
+/// idealized rectangle through four corners if every angle is close to a right angle
+///
+/// Rectangles within the angle tolerance of the axes are snapped to them.
+fn rectangle(corners: &[Point], tolerance: f64) -> Option<Vec<Point>> {
+    let max_error = tolerance * FRAC_PI_2;
+    for i in 0..4 {
+        let (before, at, after) = (corners[(i + 3) % 4], corners[i], corners[(i + 1) % 4]);
+        let turn = wrap(direction(at, after) - direction(before, at));
+        if (turn.abs() - FRAC_PI_2).abs() > max_error {
+            return None;
+        }
+    }
+    let mut angle = direction(corners[0], corners[1]);
+    let axis = (angle / FRAC_PI_2).round() * FRAC_PI_2;
+    if (angle - axis).abs() <= max_error {
+        angle = axis;
+    }
+    let width = (distance(corners[0], corners[1]) + distance(corners[2], corners[3])) / 2.0;
+    let height = (distance(corners[1], corners[2]) + distance(corners[3], corners[0])) / 2.0;
+    // keep the drawing direction, clockwise or counterclockwise
+    let side = wrap(direction(corners[1], corners[2]) - direction(corners[0], corners[1])).signum();
+    let u = (angle.cos() * width / 2.0, angle.sin() * width / 2.0);
+    let v = (
+        -angle.sin() * side * height / 2.0,
+        angle.cos() * side * height / 2.0,
+    );
+    let center = corners
+        .iter()
+        .fold((0.0, 0.0), |sum, p| (sum.0 + p.0 / 4.0, sum.1 + p.1 / 4.0));
+    Some(
+        [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)]
+            .iter()
+            .map(|(a, b)| (center.0 + a * u.0 + b * v.0, center.1 + a * u.1 + b * v.1))
+            .collect(),
+    )
+}
+
+/// arrow drawn as shaft, first barb, back to the tip and second barb
+fn arrow(points: &[Point], tolerance: f64) -> Option<LineString<f64>> {
+    let (start, tip, first_barb, tip_again, second_barb) =
+        (points[0], points[1], points[2], points[3], points[4]);
+    let shaft = distance(start, tip);
+    if distance(tip, tip_again) > tolerance * shaft {
+        return None;
+    }
+    let back = direction(tip, start);
+    let barb_angle = |barb: Point| {
+        if distance(tip, barb) > shaft / 2.0 {
+            None
+        } else {
+            Some(wrap(direction(tip, barb) - back))
+        }
+    };
+    let (first, second) = (barb_angle(first_barb)?, barb_angle(second_barb)?);
+    let is_barb = |angle: f64| angle.abs() > tolerance * FRAC_PI_2 && angle.abs() < FRAC_PI_2;
+    if !(is_barb(first) && is_barb(second)) || first.signum() == second.signum() {
+        return None;
+    }
+    let drag = ShapeDrag {
+        shape: Shape::Arrow,
+        start,
+        end: tip,
+    };
+    Some(drag.outline(ShapeModifiers::default()))
+}
+
+/// closed polyline through `corners`
+fn close(mut corners: Vec<Point>) -> LineString<f64> {
+    corners.push(corners[0]);
+    corners.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `corners` joined by straight segments of many points, each moved by up to `amount` in a
+    /// fixed pseudo random direction so the result is the same on every run
+    fn jittered(corners: &[Point], amount: f64) -> LineString<f64> {
+        let mut points = Vec::new();
+        for segment in corners.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+            for step in 0..20 {
+                let t = step as f64 / 20.0;
+                points.push((a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t));
+            }
+        }
+        points.push(*corners.last().unwrap());
+        points
+            .into_iter()
+            .enumerate()
+            .map(|(i, (x, y))| {
+                let noise = |seed: f64| ((i as f64 * 12.9898 + seed).sin() * 43758.5453).fract();
+                (x + noise(0.0) * amount, y + noise(78.233) * amount)
+            })
+            .collect::<Vec<_>>()
+            .into()
+    }
+
+    fn circle(center: Point, radii: (f64, f64), amount: f64) -> LineString<f64> {
+        let points = (0..=64)
+            .map(|i| {
+                let angle = 2.0 * PI * i as f64 / 64.0;
+                (
+                    center.0 + radii.0 * angle.cos(),
+                    center.1 + radii.1 * angle.sin(),
+                )
+            })
+            .collect::<Vec<_>>();
+        jittered(&points, amount)
+    }
+
+    fn assert_near(actual: Point, expected: Point, max_distance: f64) {
+        assert!(
+            distance(actual, expected) <= max_distance,
+            "{:?} is not near {:?}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn recognizes_line() {
+        let line = recognize(
+            &jittered(&[(0.0, 0.0), (100.0, 40.0)], 1.5),
+            DEFAULT_TOLERANCE,
+        )
+        .expect("line not recognized");
+        let points = to_points(&line);
+        assert_eq!(points.len(), 2);
+        assert_near(points[0], (0.0, 0.0), 3.0);
+        assert_near(points[1], (100.0, 40.0), 3.0);
+    }
+
+    #[test]
+    fn recognizes_triangle() {
+        let corners = [(0.0, 0.0), (100.0, 0.0), (50.0, 80.0), (0.0, 0.0)];
+        let triangle = recognize(&jittered(&corners, 1.5), DEFAULT_TOLERANCE)
+            .expect("triangle not recognized");
+        let points = to_points(&triangle);
+        assert_eq!(points.len(), 4);
+        assert_eq!(points[0], points[3]);
+    }
+
+    #[test]
+    fn recognizes_rectangle() {
+        let corners = [
+            (0.0, 0.0),
+            (120.0, 0.0),
+            (120.0, 80.0),
+            (0.0, 80.0),
+            (0.0, 0.0),
+        ];
+        let rectangle = recognize(&jittered(&corners, 1.5), DEFAULT_TOLERANCE)
+            .expect("rectangle not recognized");
+        let points = to_points(&rectangle);
+        assert_eq!(points.len(), 5);
+        for (point, corner) in points.iter().zip(&corners) {
+            assert_near(*point, *corner, 4.0);
+        }
+        // snapped to the axes
+        assert!((points[0].1 - points[1].1).abs() < 1e-9);
+        assert!((points[1].0 - points[2].0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn recognizes_circle() {
+        let ellipse = recognize(&circle((50.0, 50.0), (40.0, 40.0), 1.5), DEFAULT_TOLERANCE)
+            .expect("circle not recognized");
+        for point in to_points(&ellipse) {
+            assert!((distance(point, (50.0, 50.0)) - 40.0).abs() < 2.0);
+        }
+    }
+
+    #[test]
+    fn recognizes_arrow() {
+        let corners = [
+            (0.0, 0.0),
+            (100.0, 0.0),
+            (85.0, 10.0),
+            (100.0, 0.0),
+            (85.0, -10.0),
+        ];
+        let arrow =
+            recognize(&jittered(&corners, 0.5), DEFAULT_TOLERANCE).expect("arrow not recognized");
+        let points = to_points(&arrow);
+        assert_eq!(points.len(), 5);
+        assert_near(points[0], (0.0, 0.0), 2.0);
+        assert_near(points[1], (100.0, 0.0), 2.0);
+        assert_eq!(points[1], points[3]);
+    }
+
+    #[test]
+    fn rejects_scribble() {
+        let corners = [
+            (0.0, 0.0),
+            (60.0, 10.0),
+            (10.0, 30.0),
+            (70.0, 50.0),
+            (5.0, 60.0),
+            (50.0, 90.0),
+        ];
+        assert!(recognize(&jittered(&corners, 1.5), DEFAULT_TOLERANCE).is_none());
+    }
+
+    #[test]
+    fn tolerance_decides_on_slanted_rectangle() {
+        // parallelogram whose corners are 10° off a right angle
+        let slant = 80f64.to_radians();
+        let (dx, dy) = (60.0 * slant.cos(), 60.0 * slant.sin());
+        let corners = [
+            (0.0, 0.0),
+            (100.0, 0.0),
+            (100.0 + dx, dy),
+            (dx, dy),
+            (0.0, 0.0),
+        ];
+        let line = jittered(&corners, 0.0);
+        assert!(recognize(&line, STRICT_TOLERANCE).is_none());
+        assert!(recognize(&line, DEFAULT_TOLERANCE).is_some());
+        assert!(recognize(&line, LOOSE_TOLERANCE).is_some());
+    }
+
+    #[test]
+    fn tolerance_decides_on_flattened_circle() {
+        let line = circle((50.0, 50.0), (50.0, 38.0), 0.0);
+        let is_circle =
+            |tolerance| recognize(&line, tolerance).map_or(false, |shape| shape.0.len() > 5);
+        assert!(!is_circle(STRICT_TOLERANCE));
+        assert!(is_circle(LOOSE_TOLERANCE));
+    }
+}
@@ -0,0 +1,277 @@
+use euclid::default::Transform2D;
+use euclid::Angle;
+use geo::algorithm::contains::Contains;
+use geo::{LineString, Point, Polygon};
+use gtk::cairo::Context;
+use rstar::{Envelope, RTreeObject, AABB};
+use serde::{Deserialize, Serialize};
+
+use crate::quadtree::{Element, Page, Viewport};
+
+/// side length of the square handles around a selection in screen pixels
+pub const HANDLE_SIZE: f64 = 8.0;
+/// distance of the rotation handle above the selection in screen pixels
+pub const ROTATION_HANDLE_OFFSET: f64 = 24.0;
+/// smallest factor a selection is scaled by while keeping its aspect ratio, so dragging past the
+/// anchor neither collapses nor mirrors it
+const MIN_ASPECT_SCALE: f64 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionKind {
+    Rectangle,
+    Lasso,
+}
+
+/// copies of the selected elements, which stay in the document
+///
+/// Coordinates are relative to the page the elements are on, viewports passed to the methods have
+/// to map onto that page.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Selection {
+    /// index of the page the elements are on
+    pub page: usize,
+    /// index of the layer the elements are on
+    pub layer: usize,
+    pub elements: Vec<Element>,
+}
+
+impl Selection {
+    /// everything on the active layer of page `page` touching `envelope`, `None` if that is
+    /// nothing
+    pub fn in_rectangle(pages: &[Page], page: usize, envelope: &AABB<Point<f64>>) -> Option<Self> {
+        let elements = pages[page]
+            .active_layer()
+            .elements
+            .locate_in_envelope_intersecting(envelope)
+            .cloned()
+            .collect::<Vec<_>>();
+        Self::from_elements(page, pages[page].layer, elements)
+    }
+
+    /// everything on the active layer of page `page` lying completely inside the closed `lasso`,
+    /// `None` if that is nothing
+    pub fn in_lasso(pages: &[Page], page: usize, lasso: &LineString<f64>) -> Option<Self> {
+        if lasso.0.len() < 3 {
+            return None;
+        }
+        let polygon = Polygon::new(lasso.clone(), Vec::new());
+        let envelope = AABB::from_points(lasso.points_iter().collect::<Vec<_>>().iter());
+        let elements = pages[page]
+            .active_layer()
+            .elements
+            .locate_in_envelope_intersecting(&envelope)
+            .filter(|element| outline(element).iter().all(|point| polygon.contains(point)))
+            .cloned()
+            .collect::<Vec<_>>();
+        Self::from_elements(page, pages[page].layer, elements)
+    }
+
+    /// what dragging from `point` in viewport coordinates does, `None` if it misses the selection
+    pub fn hit(&self, viewport: &Viewport, point: (f64, f64)) -> Option<TransformKind> {
+        let envelope = self.envelope();
+        let lower = viewport.transform_to_viewport(envelope.lower());
+        let upper = viewport.transform_to_viewport(envelope.upper());
+        let near = |handle: (f64, f64)| {
+            (point.0 - handle.0).abs() <= HANDLE_SIZE && (point.1 - handle.1).abs() <= HANDLE_SIZE
+        };
+        let handles = handles(lower, upper);
+        if near(rotation_handle(lower, upper)) {
+            let center = ((lower.0 + upper.0) / 2.0, (lower.1 + upper.1) / 2.0);
+            return Some(TransformKind::Rotate {
+                center: viewport.normalize_from_viewport(center),
+            });
+        }
+        if let Some(i) = (0..handles.len()).find(|&i| near(handles[i])) {
+            // the handle across from the dragged one stays in place
+            let opposite = if i < 4 { (i + 2) % 4 } else { 4 + (i + 2) % 4 };
+            return Some(TransformKind::Scale {
+                anchor: viewport.normalize_from_viewport(handles[opposite]),
+                handle: viewport.normalize_from_viewport(handles[i]),
+                keep_aspect: i < 4,
+            });
+        }
+        if (lower.0..=upper.0).contains(&point.0) && (lower.1..=upper.1).contains(&point.1) {
+            return Some(TransformKind::Move);
+        }
+        None
+    }
+
+    pub fn transformed(&self, transform: &Transform2D<f64>) -> Selection {
+        Selection {
+            page: self.page,
+            layer: self.layer,
+            elements: self
+                .elements
+                .iter()
+                .map(|element| element.transformed(transform))
+                .collect(),
+        }
+    }
+
+    fn from_elements(page: usize, layer: usize, elements: Vec<Element>) -> Option<Self> {
+        if elements.is_empty() {
+            None
+        } else {
+            Some(Selection {
+                page,
+                layer,
+                elements,
+            })
+        }
+    }
+
+    /// bounding box of all selected elements
+    pub fn envelope(&self) -> AABB<Point<f64>> {
+        self.elements
+            .iter()
+            .map(RTreeObject::envelope)
+            .fold(AABB::new_empty(), |a, b| a.merged(&b))
+    }
+
+    /// dashed bounding box with handles on the corners and edges and a rotation handle on top
+    pub fn draw(&self, cairo_context: &Context, viewport: &Viewport) {
+        let envelope = self.envelope();
+        let (x1, y1) = viewport.transform_to_viewport(envelope.lower());
+        let (x2, y2) = viewport.transform_to_viewport(envelope.upper());
+        cairo_context.set_source_rgb(0.2, 0.4, 0.8);
+        cairo_context.set_line_width(1.0);
+        cairo_context.set_dash(&[4.0, 4.0], 0.0);
+        cairo_context.rectangle(x1, y1, x2 - x1, y2 - y1);
+        cairo_context.stroke().unwrap();
+        cairo_context.set_dash(&[], 0.0);
+        let (rx, ry) = rotation_handle((x1, y1), (x2, y2));
+        cairo_context.move_to(rx, y1);
+        cairo_context.line_to(rx, ry);
+        cairo_context.stroke().unwrap();
+        cairo_context.arc(rx, ry, HANDLE_SIZE / 2.0, 0.0, 2.0 * std::f64::consts::PI);
+        cairo_context.set_source_rgb(1.0, 1.0, 1.0);
+        cairo_context.fill_preserve().unwrap();
+        cairo_context.set_source_rgb(0.2, 0.4, 0.8);
+        cairo_context.stroke().unwrap();
+        for (x, y) in handles((x1, y1), (x2, y2)).iter() {
+            cairo_context.rectangle(
+                x - HANDLE_SIZE / 2.0,
+                y - HANDLE_SIZE / 2.0,
+                HANDLE_SIZE,
+                HANDLE_SIZE,
+            );
+            cairo_context.set_source_rgb(1.0, 1.0, 1.0);
+            cairo_context.fill_preserve().unwrap();
+            cairo_context.set_source_rgb(0.2, 0.4, 0.8);
+            cairo_context.stroke().unwrap();
+        }
+    }
+}
+
+/// corners followed by edge midpoints of the rectangle from `lower` to `upper`
+pub fn handles(lower: (f64, f64), upper: (f64, f64)) -> [(f64, f64); 8] {
+    let middle = ((lower.0 + upper.0) / 2.0, (lower.1 + upper.1) / 2.0);
+    [
+        lower,
+        (upper.0, lower.1),
+        upper,
+        (lower.0, upper.1),
+        (middle.0, lower.1),
+        (upper.0, middle.1),
+        (middle.0, upper.1),
+        (lower.0, middle.1),
+    ]
+}
+
+/// centre of the rotation handle above the rectangle from `lower` to `upper`
+fn rotation_handle(lower: (f64, f64), upper: (f64, f64)) -> (f64, f64) {
+    ((lower.0 + upper.0) / 2.0, lower.1 - ROTATION_HANDLE_OFFSET)
+}
+
+/// what dragging the selection does
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransformKind {
+    Move,
+    /// scale away from `anchor` so that `handle` follows the pointer, in document coordinates
+    Scale {
+        anchor: (f64, f64),
+        handle: (f64, f64),
+        /// corner handles scale both axes equally
+        keep_aspect: bool,
+    },
+    /// rotate around `center` in document coordinates
+    Rotate {
+        center: (f64, f64),
+    },
+}
+
+/// drag of the selection in progress
+#[derive(Clone, Debug, PartialEq)]
+pub struct SelectionTransform {
+    pub kind: TransformKind,
+    /// pointer position the drag started at in document coordinates
+    pub start: (f64, f64),
+    /// transform from the original to the dragged selection
+    pub transform: Transform2D<f64>,
+}
+
+impl SelectionTransform {
+    pub fn new(kind: TransformKind, start: (f64, f64)) -> Self {
+        SelectionTransform {
+            kind,
+            start,
+            transform: Transform2D::identity(),
+        }
+    }
+
+    /// recompute the transform for the pointer at `pointer` in document coordinates
+    pub fn update(&mut self, pointer: (f64, f64)) {
+        let (start, p) = (self.start, pointer);
+        self.transform = match self.kind {
+            TransformKind::Move => Transform2D::translation(p.0 - start.0, p.1 - start.1),
+            TransformKind::Scale {
+                anchor: a,
+                handle: h,
+                keep_aspect,
+            } => {
+                let (hx, hy) = (h.0 - a.0, h.1 - a.1);
+                let (px, py) = (p.0 - a.0, p.1 - a.1);
+                let (sx, sy) = if keep_aspect {
+                    // project the pointer onto the diagonal through the anchor
+                    let length = hx * hx + hy * hy;
+                    let s = if length > f64::EPSILON {
+                        ((px * hx + py * hy) / length).max(MIN_ASPECT_SCALE)
+                    } else {
+                        1.0
+                    };
+                    (s, s)
+                } else {
+                    let factor = |p: f64, h: f64| if h.abs() > f64::EPSILON { p / h } else { 1.0 };
+                    (factor(px, hx), factor(py, hy))
+                };
+                Transform2D::translation(-a.0, -a.1)
+                    .then_scale(sx, sy)
+                    .then_translate((a.0, a.1).into())
+            }
+            TransformKind::Rotate { center: c } => {
+                let angle = (p.1 - c.1).atan2(p.0 - c.0) - (start.1 - c.1).atan2(start.0 - c.0);
+                Transform2D::translation(-c.0, -c.1)
+                    .then_rotate(Angle::radians(angle))
+                    .then_translate((c.0, c.1).into())
+            }
+        };
+    }
+}
+
+/// points that have to be inside a lasso for the element to be selected
+fn outline(element: &Element) -> Vec<Point<f64>> {
+    match element {
+        Element::Stroke(ink) => ink.line.points_iter().collect(),
+        Element::Text(_) | Element::Image(_) => {
+            let envelope = element.envelope();
+            let (lower, upper) = (envelope.lower(), envelope.upper());
+            vec![
+                lower,
+                Point::new(upper.x(), lower.y()),
+                upper,
+                Point::new(lower.x(), upper.y()),
+            ]
+        }
+    }
+}
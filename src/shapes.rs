@@ -0,0 +1,108 @@
+use std::f64::consts::{FRAC_PI_4, PI};
+
+use geo::LineString;
+use serde::{Deserialize, Serialize};
+
+/// number of points an ellipse outline is approximated with
+const ELLIPSE_SEGMENTS: usize = 64;
+/// length of the arrow head barbs in the units of the dragged points
+const ARROW_HEAD_LENGTH: f64 = 15.0;
+/// angle between the arrow shaft and each barb
+const ARROW_HEAD_ANGLE: f64 = PI / 6.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Shape {
+    Line,
+    Rectangle,
+    Ellipse,
+    Arrow,
+}
+
+/// modifier keys held while dragging a shape
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ShapeModifiers {
+    /// snap lines to 45° steps, rectangles to squares and ellipses to circles
+    pub constrain: bool,
+    /// the drag starts at the centre of the shape instead of a corner
+    pub centered: bool,
+}
+
+/// shape currently being dragged out, points in viewport coordinates
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShapeDrag {
+    pub shape: Shape,
+    pub start: (f64, f64),
+    pub end: (f64, f64),
+}
+
+impl ShapeDrag {
+    pub fn new(shape: Shape, start: (f64, f64)) -> Self {
+        ShapeDrag {
+            shape,
+            start,
+            end: start,
+        }
+    }
+
+    /// polyline tracing the shape, so it can be stored and erased like any other stroke
+    pub fn outline(&self, modifiers: ShapeModifiers) -> LineString<f64> {
+        let (start, end) = self.corners(modifiers);
+        let points = match self.shape {
+            Shape::Line => vec![start, end],
+            Shape::Rectangle => vec![start, (end.0, start.1), end, (start.0, end.1), start],
+            Shape::Ellipse => {
+                let center = ((start.0 + end.0) / 2.0, (start.1 + end.1) / 2.0);
+                let radius = ((end.0 - start.0) / 2.0, (end.1 - start.1) / 2.0);
+                (0..=ELLIPSE_SEGMENTS)
+                    .map(|i| {
+                        let angle = 2.0 * PI * i as f64 / ELLIPSE_SEGMENTS as f64;
+                        (
+                            center.0 + radius.0 * angle.cos(),
+                            center.1 + radius.1 * angle.sin(),
+                        )
+                    })
+                    .collect()
+            }
+            Shape::Arrow => {
+                let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                let length = dx.hypot(dy);
+                let head = ARROW_HEAD_LENGTH.min(length / 3.0);
+                let angle = dy.atan2(dx) + PI;
+                let barb = |side: f64| {
+                    let angle = angle + side * ARROW_HEAD_ANGLE;
+                    (end.0 + head * angle.cos(), end.1 + head * angle.sin())
+                };
+                // draw the head in one go by returning to the tip between the barbs
+                vec![start, end, barb(1.0), end, barb(-1.0)]
+            }
+        };
+        points.into()
+    }
+
+    /// start and end point after applying the modifiers
+    fn corners(&self, modifiers: ShapeModifiers) -> ((f64, f64), (f64, f64)) {
+        let (mut dx, mut dy) = (self.end.0 - self.start.0, self.end.1 - self.start.1);
+        if modifiers.constrain {
+            match self.shape {
+                Shape::Line | Shape::Arrow => {
+                    let length = dx.hypot(dy);
+                    let angle = (dy.atan2(dx) / FRAC_PI_4).round() * FRAC_PI_4;
+                    dx = length * angle.cos();
+                    dy = length * angle.sin();
+                }
+                Shape::Rectangle | Shape::Ellipse => {
+                    let side = dx.abs().max(dy.abs());
+                    dx = side.copysign(dx);
+                    dy = side.copysign(dy);
+                }
+            }
+        }
+        let end = (self.start.0 + dx, self.start.1 + dy);
+        if modifiers.centered {
+            ((self.start.0 - dx, self.start.1 - dy), end)
+        } else {
+            (self.start, end)
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::Path;
+
+use euclid::default::Transform2D;
+use geo::Point;
+use rstar::{Envelope, RTree, RTreeObject, AABB};
+use serde::{Deserialize, Serialize, Serializer};
+use serde_json::Value;
+
+use crate::background::Background;
+use crate::canvas::Layout;
+use crate::export::PaperSize;
+use crate::logic::Tool;
+use crate::quadtree::{Layer, Page, StrokeStyle};
+
+/// version written into every saved document, bump when the format changes
+pub const FORMAT_VERSION: u32 = 11;
+/// space around the content of an unbounded drawing turned into a page
+const MIGRATED_PAGE_MARGIN: f64 = 20.0;
+/// file extension of native documents
+pub const FILE_EXTENSION: &str = "xournalr";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Format(serde_json::Error),
+    MissingVersion,
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Format(err) => write!(f, "invalid document: {}", err),
+            Error::MissingVersion => write!(f, "document has no format version"),
+            Error::UnsupportedVersion(version) => {
+                write!(f, "unsupported document version {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Error::Format(err)
+    }
+}
+
+/// on-disk representation of a document
+#[derive(Serialize, Deserialize)]
+pub struct DocumentFile<'a> {
+    pub version: u32,
+    pub pages: Cow<'a, [Page]>,
+    pub layout: Layout,
+    /// viewport transform at the time of saving
+    pub transform: Transform2D<f64>,
+    pub tool: Tool,
+}
+
+impl<'a> DocumentFile<'a> {
+    pub fn new(pages: &'a [Page], layout: Layout, transform: Transform2D<f64>, tool: Tool) -> Self {
+        DocumentFile {
+            version: FORMAT_VERSION,
+            pages: Cow::Borrowed(pages),
+            layout,
+            transform,
+            tool,
+        }
+    }
+}
+
+pub fn save(path: &Path, document: &DocumentFile) -> Result<(), Error> {
+    let file = File::create(path)?;
+    serde_json::to_writer(BufWriter::new(file), document)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<DocumentFile<'static>, Error> {
+    let file = File::open(path)?;
+    let mut document: Value = serde_json::from_reader(BufReader::new(file))?;
+    let mut version = document
+        .get("version")
+        .and_then(Value::as_u64)
+        .ok_or(Error::MissingVersion)? as u32;
+    if version > FORMAT_VERSION {
+        return Err(Error::UnsupportedVersion(version));
+    }
+    while version < FORMAT_VERSION {
+        document = migrate(version, document)?;
+        version += 1;
+        document["version"] = version.into();
+    }
+    Ok(serde_json::from_value(document)?)
+}
+
+/// upgrade a document from `version` to `version + 1`
+///
+/// Every format change adds an arm here that rewrites the raw JSON, so old files keep loading.
+fn migrate(version: u32, mut document: Value) -> Result<Value, Error> {
+    match version {
+        // version 1 stored bare line strings, version 2 adds pen pressure
+        1 => {
+            map_leaves(
+                &mut document["drawing"],
+                &mut |line| serde_json::json!({ "line": line, "pressure": [] }),
+            );
+            Ok(document)
+        }
+        // version 3 adds colour, width and opacity
+        2 => {
+            let style = serde_json::to_value(StrokeStyle::default())?;
+            map_leaves(&mut document["drawing"], &mut |mut ink| {
+                ink["style"] = style.clone();
+                ink
+            });
+            Ok(document)
+        }
+        // version 4 tells pen and highlighter strokes apart
+        3 => {
+            map_leaves(&mut document["drawing"], &mut |mut ink| {
+                ink["style"]["kind"] = "pen".into();
+                ink
+            });
+            Ok(document)
+        }
+        // version 5 stores text besides strokes
+        4 => {
+            map_leaves(
+                &mut document["drawing"],
+                &mut |ink| serde_json::json!({ "stroke": ink }),
+            );
+            Ok(document)
+        }
+        // version 6 splits the unbounded drawing into pages, images need no migration
+        5 => {
+            let mut elements = Vec::new();
+            map_leaves(&mut document["drawing"].take(), &mut |element| {
+                elements.push(element);
+                Value::Null
+            });
+            let mut elements = elements
+                .into_iter()
+                .map(RawElement::new)
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut transform: Transform2D<f64> =
+                serde_json::from_value(document["transform"].clone())?;
+            // pages are written as they were in version 6, later migrations extend them
+            let envelope = elements
+                .iter()
+                .map(|element| element.envelope)
+                .reduce(|a, b| a.merged(&b));
+            let size = match envelope {
+                None => PaperSize::A4,
+                Some(envelope) => {
+                    // move the content onto a page that fits it and keep it in view
+                    let (lower, upper) = (envelope.lower(), envelope.upper());
+                    let offset = (
+                        MIGRATED_PAGE_MARGIN - lower.x(),
+                        MIGRATED_PAGE_MARGIN - lower.y(),
+                    );
+                    transform = transform.then(&Transform2D::translation(offset.0, offset.1));
+                    for element in &mut elements {
+                        element.translate(offset);
+                    }
+                    PaperSize::Custom(
+                        upper.x() - lower.x() + 2.0 * MIGRATED_PAGE_MARGIN,
+                        upper.y() - lower.y() + 2.0 * MIGRATED_PAGE_MARGIN,
+                    )
+                }
+            };
+            let elements = RTree::bulk_load(elements);
+            if let Some(fields) = document.as_object_mut() {
+                fields.remove("drawing");
+            }
+            let page = serde_json::json!({
+                "size": serde_json::to_value(size)?,
+                "elements": serde_json::to_value(elements)?,
+            });
+            document["pages"] = serde_json::json!([page]);
+            document["transform"] = serde_json::to_value(transform)?;
+            Ok(document)
+        }
+        // version 7 gives every page a background, previously they were plain white
+        6 => {
+            let background = serde_json::to_value(Background::default())?;
+            if let Some(pages) = document["pages"].as_array_mut() {
+                for page in pages {
+                    page["background"] = background.clone();
+                }
+            }
+            Ok(document)
+        }
+        // version 8 moves the elements of every page onto a single layer
+        7 => {
+            if let Some(pages) = document["pages"].as_array_mut() {
+                for page in pages {
+                    let mut layer = serde_json::to_value(Layer::new(Layer::default_name(0)))?;
+                    layer["elements"] = page["elements"].take();
+                    if let Some(fields) = page.as_object_mut() {
+                        fields.remove("elements");
+                    }
+                    page["layers"] = serde_json::json!([layer]);
+                    page["layer"] = 0.into();
+                }
+            }
+            Ok(document)
+        }
+        // version 9 can show a PDF page behind the layers of a page
+        8 => {
+            if let Some(pages) = document["pages"].as_array_mut() {
+                for page in pages {
+                    page["pdf"] = Value::Null;
+                }
+            }
+            Ok(document)
+        }
+        // version 10 can hold an infinite canvas instead of pages
+        9 => {
+            document["layout"] = serde_json::to_value(Layout::Paged)?;
+            Ok(document)
+        }
+        // version 11 stores a Pango font description in place of the font family of text
+        10 => {
+            map_leaves(&mut document["pages"], &mut |mut element| {
+                if let Some(style) = element
+                    .get_mut("text")
+                    .and_then(|text| text.get_mut("style"))
+                    .and_then(Value::as_object_mut)
+                {
+                    if let Some(family) = style.remove("family") {
+                        style.insert("font".to_owned(), family);
+                    }
+                }
+                element
+            });
+            Ok(document)
+        }
+        _ => Err(Error::UnsupportedVersion(version)),
+    }
+}
+
+/// stroke or text box of a version 5 document kept as JSON, so migrating it does not depend on
+/// how `Element` looks today
+struct RawElement {
+    value: Value,
+    envelope: AABB<Point<f64>>,
+}
+
+impl RawElement {
+    fn new(value: Value) -> Result<Self, Error> {
+        let envelope = Self::raw_envelope(&value).ok_or_else(|| {
+            Error::Format(serde::de::Error::custom(
+                "element without position or points",
+            ))
+        })?;
+        Ok(RawElement { value, envelope })
+    }
+
+    /// bounding box like `Element::envelope` computes it
+    fn raw_envelope(value: &Value) -> Option<AABB<Point<f64>>> {
+        if let Some(ink) = value.get("stroke") {
+            let half = ink["style"]["width"].as_f64()? / 2.0;
+            let points = ink["line"]
+                .as_array()?
+                .iter()
+                .map(|c| Some(Point::new(c["x"].as_f64()?, c["y"].as_f64()?)))
+                .collect::<Option<Vec<_>>>()?;
+            if points.is_empty() {
+                return None;
+            }
+            let envelope = AABB::from_points(points.iter());
+            let (lower, upper) = (envelope.lower(), envelope.upper());
+            Some(AABB::from_corners(
+                (lower.x() - half, lower.y() - half).into(),
+                (upper.x() + half, upper.y() + half).into(),
+            ))
+        } else {
+            let text = value.get("text")?;
+            let (x, y) = (text["position"][0].as_f64()?, text["position"][1].as_f64()?);
+            let (width, height) = (text["extent"][0].as_f64()?, text["extent"][1].as_f64()?);
+            Some(AABB::from_corners(
+                (x, y).into(),
+                (x + width, y + height).into(),
+            ))
+        }
+    }
+
+    fn translate(&mut self, (dx, dy): (f64, f64)) {
+        let shift = |value: &mut Value, by: f64| {
+            if let Some(coordinate) = value.as_f64() {
+                *value = (coordinate + by).into();
+            }
+        };
+        if let Some(ink) = self.value.get_mut("stroke") {
+            if let Some(points) = ink["line"].as_array_mut() {
+                for point in points {
+                    shift(&mut point["x"], dx);
+                    shift(&mut point["y"], dy);
+                }
+            }
+        } else if let Some(text) = self.value.get_mut("text") {
+            shift(&mut text["position"][0], dx);
+            shift(&mut text["position"][1], dy);
+        }
+        let (lower, upper) = (self.envelope.lower(), self.envelope.upper());
+        self.envelope = AABB::from_corners(
+            (lower.x() + dx, lower.y() + dy).into(),
+            (upper.x() + dx, upper.y() + dy).into(),
+        );
+    }
+}
+
+impl RTreeObject for RawElement {
+    type Envelope = AABB<Point<f64>>;
+
+    fn envelope(&self) -> Self::Envelope {
+        self.envelope
+    }
+}
+
+impl Serialize for RawElement {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(serializer)
+    }
+}
+
+/// replace every element stored in a serialized R-tree
+fn map_leaves(node: &mut Value, f: &mut impl FnMut(Value) -> Value) {
+    match node {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if key == "Leaf" {
+                    *value = f(value.take());
+                } else {
+                    map_leaves(value, f);
+                }
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                map_leaves(value, f);
+            }
+        }
+        _ => (),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::quadtree::{Element, Ink, Rgba, Stroke};
+    use crate::text::{TextBox, TextStyle};
+    use std::path::PathBuf;
+
+    fn sample(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/data")
+            .join(name)
+    }
+
+    #[test]
+    fn saved_documents_load_unchanged() {
+        let mut ink = Ink::new(StrokeStyle::default());
+        ink.add(10.0, 10.0, Some(0.5));
+        ink.add(40.0, 25.0, Some(0.8));
+        let text = TextBox {
+            position: (30.0, 60.0),
+            text: "note".to_string(),
+            style: TextStyle::default(),
+            extent: (24.0, 12.0),
+        };
+        let mut first = Page::new(PaperSize::A4);
+        first.layers[0].elements.insert(Element::Stroke(ink));
+        first.layers.push(Layer::new(Layer::default_name(1)));
+        first.layers[1].elements.insert(Element::Text(text));
+        first.layer = 1;
+        let pages = vec![first, Page::new(PaperSize::Letter)];
+        let transform = Transform2D::translation(5.0, -3.0);
+
+        let path = std::env::temp_dir().join(format!("xournalr-{}-saved", std::process::id()));
+        save(
+            &path,
+            &DocumentFile::new(&pages, Layout::Paged, transform, Tool::Highlighter),
+        )
+        .unwrap();
+        let loaded = load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.version, FORMAT_VERSION);
+        assert_eq!(loaded.layout, Layout::Paged);
+        assert_eq!(loaded.transform, transform);
+        assert!(loaded.tool == Tool::Highlighter);
+        // pages have no equality, their serialized form has
+        assert_eq!(
+            serde_json::to_value(&loaded.pages).unwrap(),
+            serde_json::to_value(&pages).unwrap()
+        );
+    }
+
+    #[test]
+    fn version_1_documents_load_onto_a_page() {
+        let document = load(&sample("version1.xournalr")).unwrap();
+        assert_eq!(document.version, FORMAT_VERSION);
+        assert_eq!(document.layout, Layout::Paged);
+        assert!(document.tool == Tool::Pen);
+        // the content spans (9, 9) to (61, 41) including half the line width
+        let offset = MIGRATED_PAGE_MARGIN - 9.0;
+        assert_eq!(
+            document.transform,
+            Transform2D::translation(5.0 + offset, -3.0 + offset)
+        );
+
+        assert_eq!(document.pages.len(), 1);
+        let page = &document.pages[0];
+        assert_eq!(
+            page.size,
+            PaperSize::Custom(
+                52.0 + 2.0 * MIGRATED_PAGE_MARGIN,
+                32.0 + 2.0 * MIGRATED_PAGE_MARGIN
+            )
+        );
+        assert!(page.pdf.is_none());
+        assert_eq!(page.layers.len(), 1);
+        let mut strokes = page.layers[0]
+            .elements
+            .iter()
+            .map(|element| match element {
+                Element::Stroke(ink) => ink.clone(),
+                other => panic!("expected a stroke, found {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        strokes.sort_by(|a, b| a.line.0[0].x.partial_cmp(&b.line.0[0].x).unwrap());
+        assert_eq!(strokes.len(), 2);
+        assert_eq!(strokes[0].line.0[0], (10.0 + offset, 10.0 + offset).into());
+        assert_eq!(strokes[1].line.0[1], (60.0 + offset, 20.0 + offset).into());
+        for ink in &strokes {
+            assert!(ink.pressure.is_empty());
+            assert_eq!(ink.style, StrokeStyle::default());
+        }
+    }
+
+    #[test]
+    fn version_10_font_families_become_font_descriptions() {
+        let text = serde_json::json!({ "text": {
+            "position": [0.0, 0.0],
+            "text": "note",
+            "style": { "family": "Serif", "size": 12.0, "color": Rgba::default() },
+            "extent": [24.0, 12.0],
+        }});
+        let document =
+            serde_json::json!({ "pages": [{ "layers": [{ "elements": { "Leaf": text } }] }] });
+        let document = migrate(10, document).unwrap();
+        let style = &document["pages"][0]["layers"][0]["elements"]["Leaf"]["text"]["style"];
+        assert_eq!(style["font"], "Serif");
+        assert!(style.get("family").is_none());
+    }
+}
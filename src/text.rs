@@ -0,0 +1,147 @@
+use geo::Point;
+use gtk::cairo::{Context, Format, ImageSurface};
+use gtk::glib::translate::IntoGlib;
+use gtk::pango::{self, FontDescription, Layout, Style};
+use rstar::AABB;
+use serde::{Deserialize, Serialize};
+
+use crate::quadtree::{Rgba, Viewport};
+
+/// font used until another one is chosen
+pub const DEFAULT_FONT: &str = "Sans";
+/// font size in document units used until another one is chosen
+pub const DEFAULT_FONT_SIZE: f64 = 12.0;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TextStyle {
+    /// Pango font description without a size, e.g. "Sans Bold Italic"
+    pub font: String,
+    /// font size in document units
+    pub size: f64,
+    pub color: Rgba,
+}
+
+impl Default for TextStyle {
+    fn default() -> Self {
+        TextStyle {
+            font: DEFAULT_FONT.to_owned(),
+            size: DEFAULT_FONT_SIZE,
+            color: Rgba::new(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+}
+
+impl TextStyle {
+    /// `font` at `size`, `scale` is the number of document units per output unit
+    pub fn font_description(&self, scale: f64) -> FontDescription {
+        let mut description = FontDescription::from_string(&self.font);
+        description.set_absolute_size(self.size / scale * pango::SCALE as f64);
+        description
+    }
+
+    /// CSS declarations selecting the font at the given scale
+    pub fn css(&self, scale: f64) -> String {
+        let description = self.font_description(scale);
+        let style = match description.style() {
+            Style::Italic => "italic",
+            Style::Oblique => "oblique",
+            _ => "normal",
+        };
+        format!(
+            "font-family: \"{}\"; font-weight: {}; font-style: {}; font-size: {}px;",
+            description.family().as_deref().unwrap_or_default(),
+            description.weight().into_glib(),
+            style,
+            self.size / scale
+        )
+    }
+}
+
+/// block of text as stored in the document, lines are separated by `\n`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TextBox {
+    /// top left corner in document coordinates
+    pub position: (f64, f64),
+    pub text: String,
+    pub style: TextStyle,
+    /// width and height in document units, measured when the text is set
+    pub extent: (f64, f64),
+}
+
+impl TextBox {
+    pub fn new(position: (f64, f64), text: String, style: TextStyle) -> Self {
+        let extent = measure(&text, &style);
+        TextBox {
+            position,
+            text,
+            style,
+            extent,
+        }
+    }
+
+    pub fn envelope(&self) -> AABB<Point<f64>> {
+        let (x, y) = self.position;
+        AABB::from_corners((x, y).into(), (x + self.extent.0, y + self.extent.1).into())
+    }
+
+    pub fn draw(&self, cairo_context: &Context, viewport: &Viewport) {
+        let scale = viewport.transform.m11;
+        let (x, y) = viewport.transform_to_viewport(self.position);
+        let layout = match layout(cairo_context, &self.text, &self.style, scale) {
+            Some(layout) => layout,
+            None => {
+                log::warn!("Failed to draw text: no Pango layout");
+                return;
+            }
+        };
+        let color = self.style.color;
+        cairo_context.set_source_rgba(color.red, color.green, color.blue, color.alpha);
+        cairo_context.move_to(x, y);
+        pangocairo::show_layout(cairo_context, &layout);
+    }
+}
+
+/// `text` laid out for drawing on `cairo_context`, `scale` is the number of document units per
+/// output unit
+fn layout(cairo_context: &Context, text: &str, style: &TextStyle, scale: f64) -> Option<Layout> {
+    let layout = pangocairo::create_layout(cairo_context)?;
+    layout.set_font_description(Some(&style.font_description(scale)));
+    layout.set_text(text);
+    Some(layout)
+}
+
+/// size of the laid out text in document units
+fn measure(text: &str, style: &TextStyle) -> (f64, f64) {
+    let fallback = (0.0, style.size);
+    let cairo_context = match ImageSurface::create(Format::ARgb32, 1, 1)
+        .and_then(|surface| Context::new(&surface))
+    {
+        Ok(cairo_context) => cairo_context,
+        Err(_) => return fallback,
+    };
+    match layout(&cairo_context, text, style, 1.0) {
+        Some(layout) => {
+            let (width, height) = layout.size();
+            (
+                width as f64 / pango::SCALE as f64,
+                height as f64 / pango::SCALE as f64,
+            )
+        }
+        None => fallback,
+    }
+}
+
+/// text box currently being typed into
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEdit {
+    /// index of the page the text box is on
+    pub page: usize,
+    /// index of the layer the text box is on
+    pub layer: usize,
+    /// top left corner in page coordinates
+    pub position: (f64, f64),
+    pub text: String,
+    pub style: TextStyle,
+    /// text box being edited, taken out of the document until the edit is finished
+    pub original: Option<TextBox>,
+}
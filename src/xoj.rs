@@ -0,0 +1,366 @@
+use std::fmt;
+use std::path::Path;
+
+use crate::quadtree::Page;
+use crate::xopp::{self, Error, XoppBackground, XoppLayer, XoppPage};
+
+/// file extension of legacy Xournal documents
+pub const FILE_EXTENSION: &str = "xoj";
+
+/// something in a `.xoj` file that could not be carried over into the document
+#[derive(Clone, Debug, PartialEq)]
+pub enum Warning {
+    /// element the importer does not understand
+    UnknownElement { page: usize, element: String },
+    /// stroke drawn with a tool other than pen or highlighter
+    UnsupportedTool { page: usize, tool: String },
+    /// page background other than a solid colour with a known pattern
+    UnsupportedBackground { page: usize, background: Background },
+    /// stroke without any points
+    EmptyStroke { page: usize },
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Warning::UnknownElement { page, element } => {
+                write!(f, "page {}: unsupported element <{}>", page + 1, element)
+            }
+            Warning::UnsupportedTool { page, tool } => {
+                write!(f, "page {}: unsupported stroke tool {:?}", page + 1, tool)
+            }
+            Warning::UnsupportedBackground { page, background } => {
+                write!(
+                    f,
+                    "page {}: unsupported background {:?}",
+                    page + 1,
+                    background
+                )
+            }
+            Warning::EmptyStroke { page } => write!(f, "page {}: stroke without points", page + 1),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Background {
+    Solid {
+        color: String,
+        style: String,
+    },
+    Pdf {
+        filename: Option<String>,
+        page: Option<u32>,
+    },
+    Pixmap {
+        filename: Option<String>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct XojPage {
+    pub width: f64,
+    pub height: f64,
+    pub background: Background,
+    pub layers: Vec<XoppLayer>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct XojDocument {
+    pub pages: Vec<XojPage>,
+    /// problems found while parsing
+    pub warnings: Vec<Warning>,
+}
+
+pub fn read(path: &Path) -> Result<XojDocument, Error> {
+    let xml = xopp::read_xml(path)?;
+    parse(&xml)
+}
+
+pub fn parse(xml: &str) -> Result<XojDocument, Error> {
+    let document = roxmltree::Document::parse(xml)?;
+    let root = document.root_element();
+    if !root.has_tag_name("xournal") {
+        return Err(Error::Format(format!(
+            "unexpected root element <{}>",
+            root.tag_name().name()
+        )));
+    }
+    let mut result = XojDocument::default();
+    for page in root.children().filter(|n| n.has_tag_name("page")) {
+        let index = result.pages.len();
+        let mut background = Background::Solid {
+            color: "white".to_owned(),
+            style: "plain".to_owned(),
+        };
+        let mut layers = Vec::new();
+        for child in page.children().filter(roxmltree::Node::is_element) {
+            match child.tag_name().name() {
+                "background" => background = parse_background(&child),
+                "layer" => layers.push(parse_layer(&child, index, &mut result.warnings)?),
+                element => result.warnings.push(Warning::UnknownElement {
+                    page: index,
+                    element: element.to_owned(),
+                }),
+            }
+        }
+        result.pages.push(XojPage {
+            width: parse_number(&page, "width")?,
+            height: parse_number(&page, "height")?,
+            background,
+            layers,
+        });
+    }
+    Ok(result)
+}
+
+fn parse_number(node: &roxmltree::Node, name: &str) -> Result<f64, Error> {
+    node.attribute(name)
+        .and_then(|value| value.trim().parse().ok())
+        .ok_or_else(|| {
+            Error::Format(format!(
+                "<{}> with missing or invalid {} attribute",
+                node.tag_name().name(),
+                name
+            ))
+        })
+}
+
+fn parse_background(node: &roxmltree::Node) -> Background {
+    let filename = node.attribute("filename").map(str::to_owned);
+    match node.attribute("type") {
+        Some("pdf") => Background::Pdf {
+            filename,
+            page: node.attribute("pageno").and_then(|n| n.parse().ok()),
+        },
+        Some("pixmap") => Background::Pixmap { filename },
+        _ => Background::Solid {
+            color: node.attribute("color").unwrap_or("white").to_owned(),
+            style: node.attribute("style").unwrap_or("plain").to_owned(),
+        },
+    }
+}
+
+fn parse_layer(
+    node: &roxmltree::Node,
+    page: usize,
+    warnings: &mut Vec<Warning>,
+) -> Result<XoppLayer, Error> {
+    let mut layer = XoppLayer::default();
+    for child in node.children().filter(roxmltree::Node::is_element) {
+        match child.tag_name().name() {
+            "stroke" => {
+                let mut stroke = match xopp::parse_stroke(&child)? {
+                    Some(stroke) => stroke,
+                    None => {
+                        warnings.push(Warning::EmptyStroke { page });
+                        continue;
+                    }
+                };
+                match stroke.tool.as_str() {
+                    "pen" | "highlighter" => {
+                        stroke.color = color_to_hex(&stroke.color);
+                        layer.strokes.push(stroke);
+                    }
+                    tool => warnings.push(Warning::UnsupportedTool {
+                        page,
+                        tool: tool.to_owned(),
+                    }),
+                }
+            }
+            "text" => {
+                let mut text = xopp::parse_text(&child)?;
+                text.color = color_to_hex(child.attribute("color").unwrap_or("black"));
+                layer.texts.push(text);
+            }
+            "image" => layer.images.push(xopp::parse_image(&child)?),
+            element => warnings.push(Warning::UnknownElement {
+                page,
+                element: element.to_owned(),
+            }),
+        }
+    }
+    Ok(layer)
+}
+
+/// translate Xournal's predefined colour names into `#rrggbbaa`
+fn color_to_hex(color: &str) -> String {
+    match color {
+        "black" => "#000000ff",
+        "blue" => "#3333ccff",
+        "red" => "#ff0000ff",
+        "green" => "#008000ff",
+        "gray" => "#808080ff",
+        "lightblue" => "#00c0ffff",
+        "lightgreen" => "#00ff00ff",
+        "magenta" => "#ff00ffff",
+        "orange" => "#ff8000ff",
+        "yellow" => "#ffff00ff",
+        "white" => "#ffffffff",
+        other => other,
+    }
+    .to_owned()
+}
+
+/// translate Xournal's predefined paper colour names into `#rrggbbaa`, they are paler than the
+/// pen colours of the same name
+fn background_color_to_hex(color: &str) -> String {
+    match color {
+        "white" => "#ffffffff",
+        "blue" => "#a0e8ffff",
+        "pink" => "#ffc0d4ff",
+        "green" => "#80ffc0ff",
+        "orange" => "#ffc080ff",
+        "yellow" => "#ffff80ff",
+        other => other,
+    }
+    .to_owned()
+}
+
+/// convert the pages like `xopp::import`, returning everything the document could not hold
+pub fn import(document: XojDocument) -> (Vec<Page>, Vec<Warning>) {
+    let mut warnings = document.warnings;
+    let mut pages = Vec::new();
+    for (index, page) in document.pages.into_iter().enumerate() {
+        let background = match &page.background {
+            Background::Solid { color, style } if xopp::style_to_pattern(style).is_some() => {
+                XoppBackground {
+                    color: background_color_to_hex(color),
+                    style: style.clone(),
+                }
+            }
+            background => {
+                warnings.push(Warning::UnsupportedBackground {
+                    page: index,
+                    background: background.clone(),
+                });
+                XoppBackground::default()
+            }
+        };
+        pages.push(XoppPage {
+            width: page.width,
+            height: page.height,
+            background,
+            layers: page.layers,
+        });
+    }
+    (xopp::import(pages), warnings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const DOCUMENT: &str = r##"<?xml version="1.0" standalone="no"?>
+<xournal version="0.4.8">
+<title>Xournal document - see http://math.mit.edu/~auroux/software/xournal/</title>
+<page width="612.00" height="792.00">
+<background type="solid" color="blue" style="ruled" />
+<layer>
+<stroke tool="pen" color="red" width="1.41"> 10.0 20.0 30.0 40.0 </stroke>
+<stroke tool="highlighter" color="#ffff0080" width="8.50"> 50.0 60.0 70.0 80.0 </stroke>
+</layer>
+</page>
+</xournal>
+"##;
+
+    #[test]
+    fn parse_reads_pages_and_strokes() {
+        let document = parse(DOCUMENT).unwrap();
+        assert!(document.warnings.is_empty());
+        assert_eq!(document.pages.len(), 1);
+        let page = &document.pages[0];
+        assert_eq!((page.width, page.height), (612.0, 792.0));
+        assert_eq!(
+            page.background,
+            Background::Solid {
+                color: "blue".to_owned(),
+                style: "ruled".to_owned(),
+            }
+        );
+        assert_eq!(page.layers.len(), 1);
+        let strokes = &page.layers[0].strokes;
+        assert_eq!(strokes.len(), 2);
+        assert_eq!(strokes[0].tool, "pen");
+        // colour names become the colours Xournal draws them in
+        assert_eq!(strokes[0].color, "#ff0000ff");
+        assert_eq!(strokes[0].widths, vec![1.41]);
+        assert_eq!(
+            strokes[0].points.0,
+            vec![(10.0, 20.0).into(), (30.0, 40.0).into()]
+        );
+        assert_eq!(strokes[1].tool, "highlighter");
+        assert_eq!(strokes[1].color, "#ffff0080");
+    }
+
+    #[test]
+    fn read_accepts_gzip_compressed_files() {
+        let path = std::env::temp_dir().join(format!("xournalr-{}-gzip.xoj", std::process::id()));
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(DOCUMENT.as_bytes()).unwrap();
+        std::fs::write(&path, encoder.finish().unwrap()).unwrap();
+        let document = read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(document.unwrap(), parse(DOCUMENT).unwrap());
+    }
+
+    #[test]
+    fn parse_warns_about_what_it_can_not_import() {
+        let xml = r#"<xournal>
+<page width="100" height="100">
+<layer>
+<stroke tool="eraser" color="white" width="5.0"> 1 1 2 2 </stroke>
+<stroke tool="pen" color="black" width="1.0"></stroke>
+<polygon />
+</layer>
+<comment />
+</page>
+</xournal>"#;
+        let document = parse(xml).unwrap();
+        assert_eq!(
+            document.warnings,
+            vec![
+                Warning::UnsupportedTool {
+                    page: 0,
+                    tool: "eraser".to_owned(),
+                },
+                Warning::EmptyStroke { page: 0 },
+                Warning::UnknownElement {
+                    page: 0,
+                    element: "polygon".to_owned(),
+                },
+                Warning::UnknownElement {
+                    page: 0,
+                    element: "comment".to_owned(),
+                },
+            ]
+        );
+        assert!(document.pages[0].layers[0].strokes.is_empty());
+    }
+
+    #[test]
+    fn import_warns_about_pdf_backgrounds() {
+        let xml = r#"<xournal>
+<page width="100" height="100">
+<background type="pdf" domain="absolute" filename="paper.pdf" pageno="1" />
+<layer />
+</page>
+</xournal>"#;
+        let (pages, warnings) = import(parse(xml).unwrap());
+        assert_eq!(pages.len(), 1);
+        assert_eq!(
+            warnings,
+            vec![Warning::UnsupportedBackground {
+                page: 0,
+                background: Background::Pdf {
+                    filename: Some("paper.pdf".to_owned()),
+                    page: Some(1),
+                },
+            }]
+        );
+    }
+}
@@ -0,0 +1,681 @@
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use geo::LineString;
+use gtk::glib;
+use rstar::RTree;
+
+use crate::background::{Background, Pattern, DEFAULT_SPACING};
+use crate::export::PaperSize;
+use crate::image::Image;
+use crate::quadtree::{Element, Ink, Layer, Page, Rgba, StrokeKind, StrokeStyle};
+use crate::text::{TextBox, TextStyle};
+
+/// file extension of Xournal++ documents
+pub const FILE_EXTENSION: &str = "xopp";
+
+const DEFAULT_COLOR: &str = "#0000ffff";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Xml(roxmltree::Error),
+    Format(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "I/O error: {}", err),
+            Error::Xml(err) => write!(f, "invalid XML: {}", err),
+            Error::Format(msg) => write!(f, "invalid document: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<roxmltree::Error> for Error {
+    fn from(err: roxmltree::Error) -> Self {
+        Error::Xml(err)
+    }
+}
+
+/// stroke as stored in a `.xopp` file, coordinates relative to its page
+#[derive(Clone, Debug, PartialEq)]
+pub struct XoppStroke {
+    pub tool: String,
+    pub color: String,
+    /// nominal width followed by optional per-point pressure widths
+    pub widths: Vec<f64>,
+    pub points: LineString<f64>,
+}
+
+/// text as stored in a `.xopp` file, position of the top left corner relative to its page
+#[derive(Clone, Debug, PartialEq)]
+pub struct XoppText {
+    pub font: String,
+    pub size: f64,
+    pub x: f64,
+    pub y: f64,
+    pub color: String,
+    pub text: String,
+}
+
+/// image as stored in a `.xopp` file, corners relative to its page
+#[derive(Clone, Debug, PartialEq)]
+pub struct XoppImage {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+    /// PNG encoded pixels
+    pub data: Vec<u8>,
+}
+
+/// solid page background as stored in a `.xopp` file
+#[derive(Clone, Debug, PartialEq)]
+pub struct XoppBackground {
+    pub color: String,
+    /// Xournal++ name of the pattern, like `lined` or `graph`
+    pub style: String,
+}
+
+impl Default for XoppBackground {
+    fn default() -> Self {
+        XoppBackground {
+            color: "#ffffffff".to_owned(),
+            style: "plain".to_owned(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct XoppLayer {
+    /// only layers renamed by the user have a name
+    pub name: Option<String>,
+    pub strokes: Vec<XoppStroke>,
+    pub texts: Vec<XoppText>,
+    pub images: Vec<XoppImage>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct XoppPage {
+    pub width: f64,
+    pub height: f64,
+    pub background: XoppBackground,
+    /// bottom layer first
+    pub layers: Vec<XoppLayer>,
+}
+
+/// read a `.xopp` file, which is usually gzipped but may also be plain XML
+pub fn read(path: &Path) -> Result<Vec<XoppPage>, Error> {
+    let xml = read_xml(path)?;
+    parse(&xml)
+}
+
+/// read a possibly gzipped XML file into a string
+pub fn read_xml(path: &Path) -> Result<String, Error> {
+    let bytes = fs::read(path)?;
+    let mut xml = String::new();
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(&bytes[..]).read_to_string(&mut xml)?;
+    } else {
+        xml = String::from_utf8(bytes).map_err(|err| Error::Format(err.to_string()))?;
+    }
+    Ok(xml)
+}
+
+pub fn parse(xml: &str) -> Result<Vec<XoppPage>, Error> {
+    let document = roxmltree::Document::parse(xml)?;
+    let root = document.root_element();
+    if !root.has_tag_name("xournal") {
+        return Err(Error::Format(format!(
+            "unexpected root element <{}>",
+            root.tag_name().name()
+        )));
+    }
+    let mut pages = Vec::new();
+    for page in root.children().filter(|n| n.has_tag_name("page")) {
+        let width = parse_attribute(&page, "width")?;
+        let height = parse_attribute(&page, "height")?;
+        let mut layers = Vec::new();
+        // other background types are drawn as plain paper
+        let background = page
+            .children()
+            .find(|n| n.has_tag_name("background"))
+            .filter(|n| n.attribute("type").map_or(true, |t| t == "solid"))
+            .map_or_else(XoppBackground::default, |n| XoppBackground {
+                color: n.attribute("color").unwrap_or("#ffffffff").to_owned(),
+                style: n.attribute("style").unwrap_or("plain").to_owned(),
+            });
+        for node in page.children().filter(|n| n.has_tag_name("layer")) {
+            let mut layer = XoppLayer {
+                name: node.attribute("name").map(str::to_owned),
+                ..XoppLayer::default()
+            };
+            for child in node.children() {
+                if child.has_tag_name("stroke") {
+                    match parse_stroke(&child)? {
+                        Some(stroke) => layer.strokes.push(stroke),
+                        None => log::warn!("Skipping stroke without points"),
+                    }
+                } else if child.has_tag_name("text") {
+                    layer.texts.push(parse_text(&child)?);
+                } else if child.has_tag_name("image") {
+                    layer.images.push(parse_image(&child)?);
+                }
+            }
+            layers.push(layer);
+        }
+        pages.push(XoppPage {
+            width,
+            height,
+            background,
+            layers,
+        });
+    }
+    Ok(pages)
+}
+
+fn parse_attribute(node: &roxmltree::Node, name: &str) -> Result<f64, Error> {
+    node.attribute(name)
+        .ok_or_else(|| {
+            Error::Format(format!(
+                "<{}> without {} attribute",
+                node.tag_name().name(),
+                name
+            ))
+        })?
+        .trim()
+        .parse()
+        .map_err(|_| Error::Format(format!("invalid {} attribute", name)))
+}
+
+pub(crate) fn parse_numbers(text: &str) -> Result<Vec<f64>, Error> {
+    text.split_whitespace()
+        .map(|n| {
+            n.parse()
+                .map_err(|_| Error::Format(format!("invalid number {:?}", n)))
+        })
+        .collect()
+}
+
+/// the stroke in `node`, `None` if it has no points
+pub(crate) fn parse_stroke(node: &roxmltree::Node) -> Result<Option<XoppStroke>, Error> {
+    let widths = parse_numbers(node.attribute("width").unwrap_or(""))?;
+    let coordinates = parse_numbers(node.text().unwrap_or(""))?;
+    if coordinates.len() % 2 != 0 {
+        return Err(Error::Format("odd number of stroke coordinates".into()));
+    }
+    if coordinates.is_empty() {
+        return Ok(None);
+    }
+    let points = coordinates
+        .chunks(2)
+        .map(|c| (c[0], c[1]))
+        .collect::<Vec<_>>()
+        .into();
+    Ok(Some(XoppStroke {
+        tool: node.attribute("tool").unwrap_or("pen").to_owned(),
+        color: node.attribute("color").unwrap_or(DEFAULT_COLOR).to_owned(),
+        widths,
+        points,
+    }))
+}
+
+pub(crate) fn parse_text(node: &roxmltree::Node) -> Result<XoppText, Error> {
+    Ok(XoppText {
+        font: node.attribute("font").unwrap_or("Sans").to_owned(),
+        size: parse_attribute(node, "size")?,
+        x: parse_attribute(node, "x")?,
+        y: parse_attribute(node, "y")?,
+        color: node.attribute("color").unwrap_or("#000000ff").to_owned(),
+        text: node.text().unwrap_or("").to_owned(),
+    })
+}
+
+pub(crate) fn parse_image(node: &roxmltree::Node) -> Result<XoppImage, Error> {
+    Ok(XoppImage {
+        left: parse_attribute(node, "left")?,
+        top: parse_attribute(node, "top")?,
+        right: parse_attribute(node, "right")?,
+        bottom: parse_attribute(node, "bottom")?,
+        data: glib::base64_decode(node.text().unwrap_or("").trim()),
+    })
+}
+
+pub fn write(path: &Path, pages: &[XoppPage]) -> Result<(), Error> {
+    let file = File::create(path)?;
+    let mut encoder = GzEncoder::new(file, Compression::default());
+    encoder.write_all(to_xml(pages).as_bytes())?;
+    encoder.finish()?;
+    Ok(())
+}
+
+pub fn to_xml(pages: &[XoppPage]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" standalone=\"no\"?>\n");
+    xml.push_str("<xournal creator=\"XournalR\" fileversion=\"4\">\n");
+    xml.push_str(
+        "<title>Xournal++ document - see https://github.com/xournalpp/xournalpp</title>\n",
+    );
+    for page in pages {
+        writeln!(
+            xml,
+            "<page width=\"{}\" height=\"{}\">",
+            page.width, page.height
+        )
+        .unwrap();
+        writeln!(
+            xml,
+            "<background type=\"solid\" color=\"{}\" style=\"{}\"/>",
+            escape(&page.background.color),
+            escape(&page.background.style)
+        )
+        .unwrap();
+        for layer in &page.layers {
+            write_layer(&mut xml, layer);
+        }
+        xml.push_str("</page>\n");
+    }
+    xml.push_str("</xournal>\n");
+    xml
+}
+
+fn write_layer(xml: &mut String, layer: &XoppLayer) {
+    match &layer.name {
+        Some(name) => writeln!(xml, "<layer name=\"{}\">", escape(name)).unwrap(),
+        None => xml.push_str("<layer>\n"),
+    }
+    for stroke in &layer.strokes {
+        let widths = stroke
+            .widths
+            .iter()
+            .map(f64::to_string)
+            .collect::<Vec<_>>()
+            .join(" ");
+        let points = stroke
+            .points
+            .0
+            .iter()
+            .map(|c| format!("{} {}", c.x, c.y))
+            .collect::<Vec<_>>()
+            .join(" ");
+        writeln!(
+            xml,
+            "<stroke tool=\"{}\" color=\"{}\" width=\"{}\">{}</stroke>",
+            stroke.tool, stroke.color, widths, points
+        )
+        .unwrap();
+    }
+    for text in &layer.texts {
+        writeln!(
+            xml,
+            "<text font=\"{}\" size=\"{}\" x=\"{}\" y=\"{}\" color=\"{}\">{}</text>",
+            escape(&text.font),
+            text.size,
+            text.x,
+            text.y,
+            text.color,
+            escape(&text.text)
+        )
+        .unwrap();
+    }
+    for image in &layer.images {
+        writeln!(
+            xml,
+            "<image left=\"{}\" top=\"{}\" right=\"{}\" bottom=\"{}\">{}</image>",
+            image.left,
+            image.top,
+            image.right,
+            image.bottom,
+            glib::base64_encode(&image.data)
+        )
+        .unwrap();
+    }
+    xml.push_str("</layer>\n");
+}
+
+/// replace the characters XML does not allow in text and attribute values
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// one page per `.xopp` page, a blank A4 page if there are none
+pub fn import(pages: Vec<XoppPage>) -> Vec<Page> {
+    let mut result = pages
+        .into_iter()
+        .map(|page| {
+            let mut layers = page
+                .layers
+                .into_iter()
+                .enumerate()
+                .map(|(index, layer)| import_layer(index, layer))
+                .collect::<Vec<_>>();
+            if layers.is_empty() {
+                layers.push(Layer::new(Layer::default_name(0)));
+            }
+            Page {
+                size: PaperSize::from_dimensions(page.width, page.height),
+                background: Background {
+                    color: Rgba::from_hex(&page.background.color)
+                        .unwrap_or_else(|| Background::default().color),
+                    pattern: style_to_pattern(&page.background.style).unwrap_or(Pattern::Plain),
+                    spacing: DEFAULT_SPACING,
+                },
+                pdf: None,
+                layers,
+                layer: 0,
+            }
+        })
+        .collect::<Vec<_>>();
+    if result.is_empty() {
+        result.push(Page::new(PaperSize::A4));
+    }
+    result
+}
+
+/// pattern drawn for a Xournal++ background style, `None` if there is none like it
+pub(crate) fn style_to_pattern(style: &str) -> Option<Pattern> {
+    match style {
+        "plain" => Some(Pattern::Plain),
+        "lined" | "ruled" => Some(Pattern::Ruled),
+        "graph" => Some(Pattern::Graph),
+        "isograph" | "isodotted" => Some(Pattern::Isometric),
+        "dotted" => Some(Pattern::Dotted),
+        "staves" => Some(Pattern::Staves),
+        _ => None,
+    }
+}
+
+fn pattern_to_style(pattern: Pattern) -> &'static str {
+    match pattern {
+        Pattern::Plain => "plain",
+        Pattern::Ruled => "lined",
+        Pattern::Graph => "graph",
+        Pattern::Isometric => "isograph",
+        Pattern::Dotted => "dotted",
+        Pattern::Staves => "staves",
+    }
+}
+
+/// layer `index` of an imported page, unnamed layers are numbered like new ones
+fn import_layer(index: usize, layer: XoppLayer) -> Layer {
+    let mut elements = Vec::new();
+    for stroke in layer.strokes {
+        elements.push(Element::Stroke(stroke_to_ink(stroke)));
+    }
+    for text in layer.texts {
+        elements.push(Element::Text(text_to_box(text)));
+    }
+    for image in layer.images {
+        elements.push(Element::Image(xopp_to_image(image)));
+    }
+    Layer {
+        elements: RTree::bulk_load(elements),
+        ..Layer::new(layer.name.unwrap_or_else(|| Layer::default_name(index)))
+    }
+}
+
+/// per-point widths are stored after the nominal width, usually one fewer than there are points
+fn stroke_to_ink(stroke: XoppStroke) -> Ink {
+    let line = stroke.points;
+    let kind = match stroke.tool.as_str() {
+        "highlighter" => StrokeKind::Highlighter,
+        _ => StrokeKind::Pen,
+    };
+    let mut style = StrokeStyle {
+        kind,
+        color: Rgba::from_hex(&stroke.color).unwrap_or_default(),
+        ..StrokeStyle::default()
+    };
+    let mut pressure = Vec::new();
+    if let Some((nominal, widths)) = stroke.widths.split_first() {
+        style.width = *nominal;
+        if !widths.is_empty() && *nominal > 0.0 {
+            pressure = widths.iter().map(|w| w / nominal).collect::<Vec<_>>();
+            let last = *pressure.last().unwrap();
+            pressure.resize(line.0.len(), last);
+        }
+    }
+    Ink {
+        line,
+        pressure,
+        style,
+    }
+}
+
+fn text_to_box(text: XoppText) -> TextBox {
+    let style = TextStyle {
+        font: text.font,
+        size: text.size,
+        color: Rgba::from_hex(&text.color).unwrap_or_else(|| TextStyle::default().color),
+    };
+    TextBox::new((text.x, text.y), text.text, style)
+}
+
+fn box_to_text(text: &TextBox) -> XoppText {
+    XoppText {
+        font: text.style.font.clone(),
+        size: text.style.size,
+        x: text.position.0,
+        y: text.position.1,
+        color: text.style.color.to_hex(),
+        text: text.text.clone(),
+    }
+}
+
+/// the size of the image element wins over the pixel size of the image
+fn xopp_to_image(image: XoppImage) -> Image {
+    Image {
+        position: (image.left, image.top),
+        size: (image.right - image.left, image.bottom - image.top),
+        data: image.data.into(),
+    }
+}
+
+fn image_to_xopp(image: &Image) -> XoppImage {
+    let (left, top) = image.position;
+    XoppImage {
+        left,
+        top,
+        right: left + image.size.0,
+        bottom: top + image.size.1,
+        data: image.data.to_vec(),
+    }
+}
+
+fn ink_to_stroke(ink: &Ink) -> XoppStroke {
+    let points = ink.line.clone();
+    let width = ink.style.width;
+    let mut widths = vec![width];
+    let segments = ink.pressure.len().saturating_sub(1);
+    widths.extend(ink.pressure[..segments].iter().map(|p| p * width));
+    let color = Rgba {
+        alpha: ink.style.color.alpha * ink.style.opacity,
+        ..ink.style.color
+    };
+    let tool = match ink.style.kind {
+        StrokeKind::Pen => "pen",
+        StrokeKind::Highlighter => "highlighter",
+    };
+    XoppStroke {
+        tool: tool.to_owned(),
+        color: color.to_hex(),
+        widths,
+        points,
+    }
+}
+
+pub fn export(pages: &[Page]) -> Vec<XoppPage> {
+    pages
+        .iter()
+        .map(|page| {
+            let (width, height) = page.size.dimensions();
+            XoppPage {
+                width,
+                height,
+                background: XoppBackground {
+                    color: page.background.color.to_hex(),
+                    style: pattern_to_style(page.background.pattern).to_owned(),
+                },
+                layers: page.layers.iter().map(export_layer).collect(),
+            }
+        })
+        .collect()
+}
+
+fn export_layer(layer: &Layer) -> XoppLayer {
+    let mut result = XoppLayer {
+        name: Some(layer.name.clone()),
+        ..XoppLayer::default()
+    };
+    for element in layer.elements.iter() {
+        match element {
+            Element::Stroke(ink) => result.strokes.push(ink_to_stroke(ink)),
+            Element::Text(text) => result.texts.push(box_to_text(text)),
+            Element::Image(image) => result.images.push(image_to_xopp(image)),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::PathBuf;
+
+    fn sample(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/data")
+            .join(name)
+    }
+
+    /// read the sample, take it through the document and back into a file, and read that again
+    fn round_trip(name: &str) -> (Vec<XoppPage>, Vec<XoppPage>) {
+        let original = read(&sample(name)).unwrap();
+        let pages = export(&import(original.clone()));
+        let path = std::env::temp_dir().join(format!("xournalr-{}-{}", std::process::id(), name));
+        write(&path, &pages).unwrap();
+        let written = read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (original, written)
+    }
+
+    /// strokes of a layer in a stable order, the document does not keep the file order
+    fn sorted_strokes(layer: &XoppLayer) -> Vec<XoppStroke> {
+        let mut strokes = layer.strokes.clone();
+        strokes.sort_by(|a, b| {
+            let (a, b) = (a.points.0[0], b.points.0[0]);
+            (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap()
+        });
+        strokes
+    }
+
+    fn assert_close(a: &[f64], b: &[f64]) {
+        assert_eq!(a.len(), b.len(), "{:?} != {:?}", a, b);
+        for (a, b) in a.iter().zip(b) {
+            assert!((a - b).abs() < 1e-9, "{:?} != {:?}", a, b);
+        }
+    }
+
+    fn assert_same_pages(original: &[XoppPage], written: &[XoppPage]) {
+        assert_eq!(original.len(), written.len());
+        for (original, written) in original.iter().zip(written) {
+            assert_close(
+                &[original.width, original.height],
+                &[written.width, written.height],
+            );
+            assert_eq!(original.background, written.background);
+            assert_eq!(original.layers.len(), written.layers.len());
+            for (original, written) in original.layers.iter().zip(&written.layers) {
+                let strokes = sorted_strokes(original);
+                let written_strokes = sorted_strokes(written);
+                assert_eq!(strokes.len(), written_strokes.len());
+                for (stroke, written) in strokes.iter().zip(&written_strokes) {
+                    assert_eq!(stroke.tool, written.tool);
+                    assert_eq!(stroke.color, written.color);
+                    assert_close(&stroke.widths, &written.widths);
+                    assert_eq!(stroke.points, written.points);
+                }
+                assert_eq!(original.texts, written.texts);
+            }
+        }
+    }
+
+    #[test]
+    fn round_trip_keeps_pressure_widths() {
+        let (original, written) = round_trip("pressure.xopp");
+        assert_eq!(original[0].layers[0].strokes[0].widths.len(), 6);
+        assert_same_pages(&original, &written);
+    }
+
+    #[test]
+    fn round_trip_keeps_layers_and_text() {
+        let (original, written) = round_trip("layers.xopp");
+        assert_eq!(original[0].layers.len(), 2);
+        assert_eq!(original[0].layers[1].texts.len(), 1);
+        assert_same_pages(&original, &written);
+        // unnamed layers get the name they are shown with
+        assert_eq!(written[0].layers[0].name.as_deref(), Some("Layer 1"));
+        assert_eq!(written[0].layers[1].name.as_deref(), Some("Notes"));
+        assert_eq!(
+            written[0].layers[1].texts[0].text,
+            "Review & annotate\nsecond line"
+        );
+    }
+
+    #[test]
+    fn parse_skips_strokes_without_points() {
+        let pages = parse(
+            r##"<xournal><page width="100" height="100"><layer>
+                <stroke tool="pen" color="#000000ff" width="1"></stroke>
+                <stroke tool="pen" color="#000000ff" width="1">10 10 20 20</stroke>
+            </layer></page></xournal>"##,
+        )
+        .unwrap();
+        let strokes = &pages[0].layers[0].strokes;
+        assert_eq!(strokes.len(), 1);
+        assert_eq!(strokes[0].points.0.len(), 2);
+    }
+
+    #[test]
+    fn import_maps_tools_and_pressure() {
+        let pages = import(read(&sample("pressure.xopp")).unwrap());
+        let mut inks = pages[0].layers[0]
+            .elements
+            .iter()
+            .filter_map(|element| match element {
+                Element::Stroke(ink) => Some(ink.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        inks.sort_by(|a, b| a.line.0[0].y.partial_cmp(&b.line.0[0].y).unwrap());
+        assert_eq!(inks.len(), 3);
+        let pressed = &inks[0];
+        assert_eq!(pressed.style.kind, StrokeKind::Pen);
+        assert_eq!(pressed.style.width, 2.26);
+        assert_eq!(pressed.pressure.len(), pressed.line.0.len());
+        assert!((pressed.pressure[0] - 0.4 / 2.26).abs() < 1e-9);
+        assert!(inks[1].pressure.is_empty());
+        assert_eq!(inks[2].style.kind, StrokeKind::Highlighter);
+        assert_eq!(inks[2].style.color.to_hex(), "#00c0ff80");
+    }
+}